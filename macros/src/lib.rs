@@ -1,21 +1,48 @@
 use proc_macro::TokenStream;
 
 mod match_nex_route;
+mod nex_error;
 mod nex_method;
+mod nex_protocol;
 mod nex_route;
 mod utils;
 
+/// Implements `Route` for a method, reading each argument after the client connection from the request
+/// and writing each value of a tuple output to the response.
+/// `client = name` also generates a `name` function that calls the method through an `RMCCaller`.
+/// `auth = required` rejects clients that haven't authenticated, and `auth = owner(pid)` also rejects
+/// clients whose pid isn't the `pid` argument.
 #[proc_macro_attribute]
 pub fn nex_method(attr: TokenStream, item: TokenStream) -> TokenStream {
     nex_method::impl_nex_method(attr, item)
 }
 
+/// Implements `Route` for every async method of a server's impl block and `Dispatch` for the protocol.
+/// Methods route to the variant named after them, such as `login_ex` to `LoginEx`, unless they have a `#[method(...)]` attribute.
+/// Async helpers that aren't protocol methods need a `#[nex_protocol(skip)]` attribute.
+/// `#[auth(required)]` and `#[auth(owner(pid))]` on a method work like `#[nex_method]`'s `auth` argument.
+#[proc_macro_attribute]
+pub fn nex_protocol(attr: TokenStream, item: TokenStream) -> TokenStream {
+    nex_protocol::impl_nex_protocol(attr, item)
+}
+
 #[proc_macro]
 pub fn nex_route(item: TokenStream) -> TokenStream {
     nex_route::impl_nex_route(item)
 }
 
+/// Routes a request to the first matching method, answering unrouted requests with Core::NotImplemented.
+/// The server's middleware runs once around the request, whether it's routed or not.
+/// Passing `exhaustive` before the methods fails to compile if a protocol's methods aren't all routed.
 #[proc_macro]
 pub fn match_nex_route(item: TokenStream) -> TokenStream {
     match_nex_route::impl_match_nex_route(item)
 }
+
+/// Derives `NexError`, `Display` and snafu context selectors for an error enum.
+/// Each variant needs a `#[nex_error(code = ...)]` with anything that converts into a `ResultCode`,
+/// such as `RendezVous::InvalidUsername`, and can set its message with `display("...", args)`.
+#[proc_macro_derive(NexError, attributes(nex_error))]
+pub fn derive_nex_error(item: TokenStream) -> TokenStream {
+    nex_error::impl_nex_error(item)
+}
@@ -8,6 +8,10 @@ use syn::{
 
 pub type EnumVariants = Punctuated<EnumVariant, Comma>;
 
+mod kw {
+    syn::custom_keyword!(exhaustive);
+}
+
 #[derive(Debug)]
 pub struct Args {
     pub server: Ident,
@@ -16,6 +20,7 @@ pub struct Args {
     _comma_2: Token![,],
     pub request: Ident,
     _comma_3: Token![,],
+    pub exhaustive: bool,
     pub variants: EnumVariants,
 }
 
@@ -28,6 +33,14 @@ impl Parse for Args {
             _comma_2: input.parse()?,
             request: input.parse()?,
             _comma_3: input.parse()?,
+            exhaustive: {
+                let exhaustive = input.peek(kw::exhaustive) && input.peek2(Token![,]);
+                if exhaustive {
+                    input.parse::<kw::exhaustive>()?;
+                    input.parse::<Token![,]>()?;
+                }
+                exhaustive
+            },
             variants: EnumVariants::parse_terminated(input)?,
         })
     }
@@ -1,8 +1,8 @@
 use super::args::Args;
-use crate::utils::enum_variant::EnumVariant;
+use crate::utils::{enum_variant::EnumVariant, middleware_impl::get_middleware_impl};
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::parse_macro_input;
+use syn::{parse_macro_input, Ident};
 
 fn get_variant_match_branch(args: &Args, variant: &EnumVariant) -> proc_macro2::TokenStream {
     let Args {
@@ -12,15 +12,48 @@ fn get_variant_match_branch(args: &Args, variant: &EnumVariant) -> proc_macro2::
         ..
     } = args;
     let variant_token = variant.token();
+    let route_trait = variant.route_trait();
 
     quote! {
-      #request if #request.is_method(#variant_token) => nex_rs::macros::nex_route![#variant_token](#server, #client, #request).await,
+      #request if #request.is_method(#variant_token) => #route_trait::respond(#server, #client, #request).await,
     }
 }
 
+/// Matches every routed variant of each protocol enum, so a missing variant fails to compile.
+fn get_exhaustive_checks(args: &Args) -> Vec<proc_macro2::TokenStream> {
+    let mut enum_idents: Vec<&Ident> = vec![];
+    for variant in &args.variants {
+        if !enum_idents.contains(&&variant.ident) {
+            enum_idents.push(&variant.ident);
+        }
+    }
+
+    enum_idents
+        .into_iter()
+        .map(|enum_ident| {
+            let variant_tokens = args
+                .variants
+                .iter()
+                .filter(|variant| variant.ident == *enum_ident)
+                .map(EnumVariant::token);
+
+            quote! {
+              const _: fn(#enum_ident) = |method| match method {
+                #(#variant_tokens)|* => {}
+              };
+            }
+        })
+        .collect()
+}
+
 pub fn impl_match_nex_route(item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(item as Args);
-    let request = &args.request;
+    let Args {
+        server,
+        client,
+        request,
+        ..
+    } = &args;
 
     let match_branches = args
         .variants
@@ -28,10 +61,27 @@ pub fn impl_match_nex_route(item: TokenStream) -> TokenStream {
         .map(|variant| get_variant_match_branch(&args, variant))
         .collect::<Vec<proc_macro2::TokenStream>>();
 
+    let exhaustive_checks = if args.exhaustive {
+        get_exhaustive_checks(&args)
+    } else {
+        vec![]
+    };
+
+    let answer = quote! {
+        match #request {
+          #(#match_branches)*
+          _ => nex_rs::server::Server::send_not_implemented(#server, #client, #request)
+            .await
+            .map(|_| Err(nex_rs::nex_types::ResultCode::from(nex_rs::nex_types::Core::NotImplemented))),
+        }
+    };
+    let routed_answer = get_middleware_impl(server, client, request, answer);
+
     quote! {
-      match #request {
-        #(#match_branches)*
-        _ => Ok(())
+      {
+        #(#exhaustive_checks)*
+
+        #routed_answer
       }
     }
     .into()
@@ -0,0 +1,74 @@
+use proc_macro2::TokenStream;
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    token::Comma,
+    Attribute, Expr, Ident, Result, Token,
+};
+
+mod kw {
+    syn::custom_keyword!(code);
+    syn::custom_keyword!(display);
+}
+
+#[derive(Debug)]
+enum Arg {
+    Code(Expr),
+    Display(TokenStream),
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(kw::code) {
+            input.parse::<kw::code>()?;
+            input.parse::<Token![=]>()?;
+            return Ok(Self::Code(input.parse()?));
+        }
+
+        if input.peek(kw::display) {
+            input.parse::<kw::display>()?;
+            let content;
+            parenthesized!(content in input);
+            return Ok(Self::Display(content.parse()?));
+        }
+
+        Err(input.error("Invalid argument"))
+    }
+}
+
+/// The arguments of a variant's `#[nex_error(...)]` attribute.
+#[derive(Debug)]
+pub struct VariantArgs {
+    pub code: Expr,
+    pub display: Option<TokenStream>,
+}
+
+impl VariantArgs {
+    pub fn from_attrs(variant: &Ident, attrs: &[Attribute]) -> Result<Self> {
+        let attr = attrs
+            .iter()
+            .find(|attr| attr.path.is_ident("nex_error"))
+            .ok_or_else(|| {
+                syn::Error::new(variant.span(), "Missing #[nex_error(code = ...)] attribute")
+            })?;
+
+        let args = attr.parse_args_with(Punctuated::<Arg, Comma>::parse_terminated)?;
+
+        let mut code: Option<Expr> = None;
+        let mut display: Option<TokenStream> = None;
+
+        for arg in args {
+            match arg {
+                Arg::Code(expr) => code = Some(expr),
+                Arg::Display(tokens) => display = Some(tokens),
+            };
+        }
+
+        Ok(Self {
+            code: code
+                .ok_or_else(|| syn::Error::new_spanned(attr, "'code' argument is required"))?,
+            display,
+        })
+    }
+}
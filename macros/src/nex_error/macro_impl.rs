@@ -0,0 +1,234 @@
+use super::args::VariantArgs;
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Result, Type, Variant, Visibility};
+
+struct ErrorVariant {
+    ident: Ident,
+    fields: Vec<(Ident, Type)>,
+    source: Option<Type>,
+    args: VariantArgs,
+}
+
+impl ErrorVariant {
+    fn new(variant: &Variant) -> Result<Self> {
+        let args = VariantArgs::from_attrs(&variant.ident, &variant.attrs)?;
+        let mut fields = vec![];
+        let mut source = None;
+
+        match &variant.fields {
+            Fields::Named(named) => {
+                for field in named.named.iter() {
+                    let ident = field.ident.clone().expect("Named fields have idents");
+                    if ident == "source" {
+                        source = Some(field.ty.clone());
+                    } else {
+                        fields.push((ident, field.ty.clone()));
+                    }
+                }
+            }
+            Fields::Unit => {}
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "NexError variants must be unit variants or have named fields",
+                ))
+            }
+        }
+
+        Ok(Self {
+            ident: variant.ident.clone(),
+            fields,
+            source,
+            args,
+        })
+    }
+
+    fn field_names(&self) -> Vec<Ident> {
+        let mut names: Vec<Ident> = self.fields.iter().map(|(ident, _)| ident.clone()).collect();
+        if self.source.is_some() {
+            names.push(format_ident!("source"));
+        }
+        names
+    }
+}
+
+fn get_display_arm(variant: &ErrorVariant) -> proc_macro2::TokenStream {
+    let ident = &variant.ident;
+    match &variant.args.display {
+        Some(display) => {
+            let field_names = variant.field_names();
+            quote! {
+                #[allow(unused_variables)]
+                Self::#ident { #(#field_names),* } => write!(f, #display),
+            }
+        }
+        None => quote! {
+            Self::#ident { .. } => write!(f, stringify!(#ident)),
+        },
+    }
+}
+
+/// Generates a snafu context selector named after the variant.
+fn get_context_selector(
+    error_ident: &Ident,
+    vis: &Visibility,
+    variant: &ErrorVariant,
+) -> proc_macro2::TokenStream {
+    let ident = &variant.ident;
+    let field_idents: Vec<&Ident> = variant.fields.iter().map(|(ident, _)| ident).collect();
+    let field_types: Vec<&Type> = variant.fields.iter().map(|(_, ty)| ty).collect();
+    let generics: Vec<Ident> = (0..variant.fields.len())
+        .map(|index| format_ident!("__T{}", index))
+        .collect();
+
+    let selector = if variant.fields.is_empty() {
+        quote! { #vis struct #ident; }
+    } else {
+        quote! {
+            #vis struct #ident<#(#generics),*> {
+                #(#vis #field_idents: #generics),*
+            }
+        }
+    };
+
+    let (source_type, source_field) = match &variant.source {
+        Some(source) => (quote!(#source), quote!(source)),
+        None => (quote!(nex_rs::snafu::NoneError), quote!()),
+    };
+
+    // Selectors for errors with a source are only used through `context`
+    let fail = match variant.source {
+        Some(_) => quote!(),
+        None => quote! {
+            #[allow(dead_code)]
+            impl<#(#generics),*> #ident<#(#generics),*> {
+                pub fn fail<__T>(self) -> Result<__T, #error_ident>
+                where
+                    #(#generics: Into<#field_types>),*
+                {
+                    Err(nex_rs::snafu::IntoError::into_error(self, nex_rs::snafu::NoneError))
+                }
+            }
+        },
+    };
+
+    quote! {
+        #[allow(dead_code)]
+        #selector
+
+        #fail
+
+        impl<#(#generics),*> nex_rs::snafu::IntoError<#error_ident> for #ident<#(#generics),*>
+        where
+            #error_ident: nex_rs::snafu::Error + nex_rs::snafu::ErrorCompat,
+            #(#generics: Into<#field_types>),*
+        {
+            type Source = #source_type;
+
+            #[allow(unused_variables)]
+            fn into_error(self, source: Self::Source) -> #error_ident {
+                #error_ident::#ident {
+                    #(#field_idents: self.#field_idents.into(),)*
+                    #source_field
+                }
+            }
+        }
+    }
+}
+
+fn get_error_impl(input: &DeriveInput) -> Result<proc_macro2::TokenStream> {
+    let error_ident = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "NexError can't be derived for generic enums",
+        ));
+    }
+
+    let variants = match &input.data {
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(ErrorVariant::new)
+            .collect::<Result<Vec<_>>>()?,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                error_ident,
+                "NexError can only be derived for enums",
+            ))
+        }
+    };
+
+    // Empty enums can't be matched by reference
+    let matched = if variants.is_empty() {
+        quote!(*self)
+    } else {
+        quote!(self)
+    };
+
+    let code_arms = variants.iter().map(|variant| {
+        let ident = &variant.ident;
+        let code = &variant.args.code;
+        quote! {
+            Self::#ident { .. } => nex_rs::nex_types::ResultCode::from(#code),
+        }
+    });
+
+    let display_arms = variants.iter().map(get_display_arm);
+
+    let source_arms = variants
+        .iter()
+        .filter(|variant| variant.source.is_some())
+        .map(|variant| {
+            let ident = &variant.ident;
+            quote! {
+                Self::#ident { source, .. } => Some(source as &(dyn nex_rs::snafu::Error + 'static)),
+            }
+        });
+
+    let context_selectors = variants
+        .iter()
+        .map(|variant| get_context_selector(error_ident, &input.vis, variant));
+
+    Ok(quote! {
+        impl nex_rs::result::NexError for #error_ident {
+            fn error_code(&self) -> nex_rs::nex_types::ResultCode {
+                match #matched {
+                    #(#code_arms)*
+                }
+            }
+        }
+
+        impl core::fmt::Display for #error_ident {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match #matched {
+                    #(#display_arms)*
+                }
+            }
+        }
+
+        impl nex_rs::snafu::Error for #error_ident {
+            fn source(&self) -> Option<&(dyn nex_rs::snafu::Error + 'static)> {
+                #[allow(unreachable_patterns)]
+                match self {
+                    #(#source_arms)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl nex_rs::snafu::ErrorCompat for #error_ident {}
+
+        #(#context_selectors)*
+    })
+}
+
+pub fn impl_nex_error(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+
+    get_error_impl(&input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
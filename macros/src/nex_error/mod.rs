@@ -0,0 +1,4 @@
+mod args;
+mod macro_impl;
+
+pub use macro_impl::*;
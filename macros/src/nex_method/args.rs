@@ -1,9 +1,9 @@
-use crate::utils::enum_variant::EnumVariant;
+use crate::utils::{auth_requirement::AuthRequirement, enum_variant::EnumVariant};
 use syn::{
     parse::{Parse, ParseStream},
     punctuated::Punctuated,
     token::Comma,
-    Path, Result, Token,
+    Ident, Path, Result, Token,
 };
 
 #[derive(Debug)]
@@ -28,9 +28,55 @@ impl Parse for MethodArg {
     }
 }
 
+#[derive(Debug)]
+pub struct ClientArg {
+    _path: Path,
+    _equals: Token![=],
+    pub ident: Ident,
+}
+
+impl Parse for ClientArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let path: Path = input.parse()?;
+        if !path.is_ident("client") {
+            return Err(input.error("Missing 'client' argument"));
+        }
+
+        Ok(Self {
+            _path: path,
+            _equals: input.parse()?,
+            ident: input.parse()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthArg {
+    _path: Path,
+    _equals: Token![=],
+    pub requirement: AuthRequirement,
+}
+
+impl Parse for AuthArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let path: Path = input.parse()?;
+        if !path.is_ident("auth") {
+            return Err(input.error("Missing 'auth' argument"));
+        }
+
+        Ok(Self {
+            _path: path,
+            _equals: input.parse()?,
+            requirement: input.parse()?,
+        })
+    }
+}
+
 #[derive(Debug)]
 enum Arg {
     Method(MethodArg),
+    Client(ClientArg),
+    Auth(AuthArg),
 }
 
 impl Parse for Arg {
@@ -42,6 +88,14 @@ impl Parse for Arg {
             return Ok(Self::Method(input.parse()?));
         }
 
+        if path.is_ident("client") {
+            return Ok(Self::Client(input.parse()?));
+        }
+
+        if path.is_ident("auth") {
+            return Ok(Self::Auth(input.parse()?));
+        }
+
         Err(error_fork.error("Invalid argument"))
     }
 }
@@ -49,6 +103,10 @@ impl Parse for Arg {
 #[derive(Debug)]
 pub struct Args {
     pub method: MethodArg,
+    /// Names the client call generated for the method, if any.
+    pub client: Option<ClientArg>,
+    /// Who can call the method, if it needs an authenticated client.
+    pub auth: Option<AuthArg>,
 }
 
 impl Parse for Args {
@@ -57,15 +115,21 @@ impl Parse for Args {
         let args = Punctuated::<Arg, Comma>::parse_terminated(input)?;
 
         let mut method: Option<MethodArg> = None;
+        let mut client: Option<ClientArg> = None;
+        let mut auth: Option<AuthArg> = None;
 
         for arg in args {
             match arg {
                 Arg::Method(method_arg) => method = Some(method_arg),
+                Arg::Client(client_arg) => client = Some(client_arg),
+                Arg::Auth(auth_arg) => auth = Some(auth_arg),
             };
         }
 
         Ok(Self {
             method: method.ok_or_else(|| error_fork.error("'method' argument is required"))?,
+            client,
+            auth,
         })
     }
 }
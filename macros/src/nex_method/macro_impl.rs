@@ -1,83 +1,64 @@
 use super::{
-    args::{Args, MethodArg},
+    args::{Args, ClientArg},
     method_signature::MethodSignature,
 };
+use crate::utils::{
+    enum_variant::EnumVariant, method_types::MethodTypes, route_impl::get_route_impl,
+};
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, ItemFn};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, ItemFn, Type, Visibility};
 
-fn get_server_impl(
-    protocol_method_fn_def: &ItemFn,
-    method: &MethodArg,
+/// Generates a function that calls the method through an `RMCCaller`, for bots and tests.
+fn get_client_call(
+    vis: &Visibility,
+    client: &ClientArg,
+    method: &EnumVariant,
+    types: &MethodTypes,
 ) -> proc_macro2::TokenStream {
-    let protocol_method_fn_ident = &protocol_method_fn_def.sig.ident;
-    let method_ident = method.variant.token();
-    let protocol_ident = &method.variant.ident;
-    let input_read_error = format!("Cannot read {} input", method.variant);
-
-    let MethodSignature { server, input } = MethodSignature::new(protocol_method_fn_def);
-
-    let input_read = match input.clone() {
-        Some(input) => quote! {
-            no_std_io::StreamReader::read_stream_le::<#input>(&mut parameters_stream)
-                .map_err(|_| #input_read_error)?
-        },
-        None => quote!(()),
+    let client_ident = &client.ident;
+    let method_token = method.token();
+    let (Some(output), Some(output_types)) = (&types.output, types.output_types()) else {
+        return syn::Error::new_spanned(client_ident, "Cannot find the method's output type")
+            .to_compile_error();
     };
 
-    let input_use = match input {
-        Some(_) => quote! {param},
-        None => quote!(),
+    let input_idents = types.input_idents();
+    let input_types = &types.inputs;
+    let output_idents: Vec<_> = (0..output_types.len())
+        .map(|index| format_ident!("output_{}", index))
+        .collect();
+    let output_value = match output {
+        Type::Tuple(_) => quote!((#(#output_idents,)*)),
+        _ => quote!(#(#output_idents)*),
     };
 
     quote! {
-        #[async_trait::async_trait]
-        impl
-            nex_rs::route::Route<
-                { <#protocol_ident as nex_rs::route::NexProtocol>::PROTOCOL_ID as u8 },
-                { #method_ident as u32 },
-            > for #server
-        {
-            async fn run(
-                &self,
-                client: &mut nex_rs::client::ClientConnection,
-                request: &nex_rs::rmc::RMCRequest,
-            ) -> nex_rs::server::ServerResult<()> {
-                let parameters = request.parameters.as_slice();
-                let mut parameters_stream = no_std_io::StreamContainer::new(parameters);
+        #vis async fn #client_ident(
+            caller: &mut impl nex_rs::rmc::RMCCaller,
+            #(#input_idents: #input_types),*
+        ) -> Result<Result<#output, nex_rs::nex_types::ResultCode>, nex_rs::result::Error> {
+            #[allow(unused_mut)]
+            let mut parameters_stream = no_std_io::StreamContainer::new(vec![]);
+            #(
+                no_std_io::StreamWriter::write_stream_le(&mut parameters_stream, &#input_idents)
+                    .map_err(nex_rs::server::Error::from)?;
+            )*
+
+            let parameters = parameters_stream.into_raw();
+            let data = match nex_rs::rmc::RMCCaller::call_with_parameters(caller, #method_token, parameters).await? {
+                Ok(data) => data,
+                Err(error_code) => return Ok(Err(error_code)),
+            };
 
-                let param = #input_read;
+            #[allow(unused_mut, unused_variables)]
+            let mut response_stream = no_std_io::StreamContainer::new(data.as_slice());
+            #(
+                let #output_idents = no_std_io::StreamReader::read_stream_le::<#output_types>(&mut response_stream)
+                    .map_err(nex_rs::server::Error::from)?;
+            )*
 
-                match #protocol_method_fn_ident(self, client, #input_use).await {
-                    Ok(response) => {
-                        let mut data = vec![];
-                        no_std_io::Writer::checked_write_le(&mut data, 0, &response);
-                        nex_rs::server::Server::send_success(
-                            self,
-                            client,
-                            request.protocol_id,
-                            request.method_id,
-                            request.call_id,
-                            data,
-                        )
-                        .await?
-                    }
-                    Err(error) => {
-                        let error_code = nex_rs::result::NexError::error_code(&error);
-                        nex_rs::server::EventHandler::on_error(self, &error.into()).await;
-                        nex_rs::server::Server::send_error(
-                            self,
-                            client,
-                            request.protocol_id,
-                            request.method_id,
-                            request.call_id,
-                            error_code.into(),
-                        )
-                        .await?
-                    }
-                }
-                Ok(())
-            }
+            Ok(Ok(#output_value))
         }
     }
 }
@@ -86,12 +67,32 @@ pub fn impl_nex_method(attr: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(attr as Args);
     let protocol_method_fn_def = parse_macro_input!(item as ItemFn);
 
-    let server_impl = get_server_impl(&protocol_method_fn_def, &args.method);
+    let signature = MethodSignature::new(&protocol_method_fn_def);
+    let protocol_method_fn_ident = &protocol_method_fn_def.sig.ident;
+    let server_impl = get_route_impl(
+        &signature.server,
+        &args.method.variant,
+        quote!(#protocol_method_fn_ident),
+        &signature.types,
+        args.auth.as_ref().map(|auth| &auth.requirement),
+    );
+
+    let client_call = match &args.client {
+        Some(client) => get_client_call(
+            &protocol_method_fn_def.vis,
+            client,
+            &args.method.variant,
+            &signature.types,
+        ),
+        None => quote!(),
+    };
 
     quote! {
         #protocol_method_fn_def
 
         #server_impl
+
+        #client_call
     }
     .into()
 }
@@ -1,3 +1,4 @@
+use crate::utils::method_types::MethodTypes;
 use std::borrow::Borrow;
 use syn::{FnArg, Ident, ItemFn, Type};
 
@@ -22,7 +23,7 @@ fn get_ident_from_ref_arg(arg: Option<&FnArg>) -> Option<&Ident> {
 
 pub struct MethodSignature {
     pub server: Ident,
-    pub input: Option<Ident>,
+    pub types: MethodTypes,
 }
 
 impl MethodSignature {
@@ -30,12 +31,12 @@ impl MethodSignature {
         let mut args_iter = fn_def.sig.inputs.iter();
 
         let server = get_ident_from_ref_arg(args_iter.next()).expect("Missing server argument");
+        // The client connection can be borrowed mutably or immutably
         get_ident_from_ref_arg(args_iter.next()).expect("Missing client connection argument");
-        let input = get_ident_from_ref_arg(args_iter.next());
 
         Self {
             server: server.clone(),
-            input: input.map(std::clone::Clone::clone),
+            types: MethodTypes::new(args_iter, &fn_def.sig.output),
         }
     }
 }
@@ -0,0 +1,188 @@
+use crate::utils::{
+    auth_requirement::AuthRequirement, enum_variant::EnumVariant, method_types::MethodTypes,
+    middleware_impl::get_middleware_impl, route_impl::get_route_impl,
+};
+use heck::ToUpperCamelCase;
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, FnArg, Ident, ImplItem, ImplItemMethod, Item, ItemImpl, Result,
+    Type,
+};
+
+struct ProtocolMethod {
+    fn_ident: Ident,
+    variant: EnumVariant,
+    types: MethodTypes,
+    auth: Option<AuthRequirement>,
+}
+
+impl ProtocolMethod {
+    /// Methods are matched to the variant named after them, or the one in their `#[method(...)]` attribute.
+    /// An `#[auth(...)]` attribute sets who can call the method, the same as `#[nex_method]`'s `auth` argument.
+    fn new(protocol: &Ident, method: &mut ImplItemMethod) -> Result<Self> {
+        let fn_ident = method.sig.ident.clone();
+        let mut variant_ident = format_ident!(
+            "{}",
+            fn_ident.to_string().to_upper_camel_case(),
+            span = fn_ident.span()
+        );
+
+        if let Some(attr) = method
+            .attrs
+            .iter()
+            .find(|attr| attr.path.is_ident("method"))
+        {
+            variant_ident = attr.parse_args()?;
+        }
+        method.attrs.retain(|attr| !attr.path.is_ident("method"));
+
+        let auth = match method.attrs.iter().find(|attr| attr.path.is_ident("auth")) {
+            Some(attr) => Some(attr.parse_args()?),
+            None => None,
+        };
+        method.attrs.retain(|attr| !attr.path.is_ident("auth"));
+
+        // Skip the receiver and client connection
+        let types = MethodTypes::new(method.sig.inputs.iter().skip(2), &method.sig.output);
+
+        Ok(Self {
+            fn_ident,
+            variant: parse_quote!(#protocol::#variant_ident),
+            types,
+            auth,
+        })
+    }
+}
+
+fn is_protocol_method(method: &ImplItemMethod) -> bool {
+    method.sig.asyncness.is_some() && matches!(method.sig.inputs.first(), Some(FnArg::Receiver(_)))
+}
+
+/// Removes a method's `#[nex_protocol(skip)]` attribute, returning whether it had one.
+/// Skipped methods aren't routed, which is useful for async helpers.
+fn take_skip_attr(method: &mut ImplItemMethod) -> Result<bool> {
+    let mut skip = false;
+
+    for attr in method
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("nex_protocol"))
+    {
+        let arg: Ident = attr.parse_args()?;
+        if arg != "skip" {
+            return Err(syn::Error::new_spanned(arg, "Expected 'skip'"));
+        }
+        skip = true;
+    }
+    method
+        .attrs
+        .retain(|attr| !attr.path.is_ident("nex_protocol"));
+
+    Ok(skip)
+}
+
+fn get_dispatch_impl(
+    protocol: &Ident,
+    server: &Type,
+    methods: &[ProtocolMethod],
+) -> proc_macro2::TokenStream {
+    let match_branches = methods.iter().map(|method| {
+        let variant_token = method.variant.token();
+        let route_trait = method.variant.route_trait();
+        let method_name = method.variant.to_string();
+
+        quote! {
+            request if request.is_method(#variant_token) => {
+                nex_rs::server::EventHandler::on_protocol_method(self, #method_name.to_string()).await;
+                #route_trait::respond(self, client, request).await
+            }
+        }
+    });
+    let answer = quote! {
+        match request {
+            #(#match_branches)*
+            _ => nex_rs::server::Server::send_not_implemented(self, client, request)
+                .await
+                .map(|_| Err(nex_rs::nex_types::ResultCode::from(nex_rs::nex_types::Core::NotImplemented))),
+        }
+    };
+    let routed_answer =
+        get_middleware_impl(&quote!(self), &quote!(client), &quote!(request), answer);
+
+    quote! {
+        #[async_trait::async_trait]
+        impl nex_rs::route::Dispatch<#protocol> for #server {
+            async fn dispatch(
+                &self,
+                client: &mut nex_rs::client::ClientConnection,
+                request: &nex_rs::rmc::RMCRequest,
+            ) -> nex_rs::server::ServerResult<()> {
+                #routed_answer
+            }
+        }
+    }
+}
+
+fn get_protocol_impl(
+    protocol: &Ident,
+    mut item_impl: ItemImpl,
+) -> Result<proc_macro2::TokenStream> {
+    if !item_impl.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item_impl.generics,
+            "#[nex_protocol] can't be used on generic impl blocks",
+        ));
+    }
+
+    let mut methods = vec![];
+    for item in item_impl.items.iter_mut() {
+        if let ImplItem::Method(method) = item {
+            if !take_skip_attr(method)? && is_protocol_method(method) {
+                methods.push(ProtocolMethod::new(protocol, method)?);
+            }
+        }
+    }
+
+    let server = item_impl.self_ty.as_ref();
+    let route_impls = methods.iter().map(|method| {
+        let fn_ident = &method.fn_ident;
+        get_route_impl(
+            server,
+            &method.variant,
+            quote!(Self::#fn_ident),
+            &method.types,
+            method.auth.as_ref(),
+        )
+    });
+    let dispatch_impl = get_dispatch_impl(protocol, server, &methods);
+
+    Ok(quote! {
+        #item_impl
+
+        #(#route_impls)*
+
+        #dispatch_impl
+    })
+}
+
+pub fn impl_nex_protocol(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let protocol = parse_macro_input!(attr as Ident);
+
+    let result = match parse_macro_input!(item as Item) {
+        Item::Impl(item_impl) => get_protocol_impl(&protocol, item_impl),
+        // Route is a foreign trait everywhere but nex_rs, so it can't be implemented for every type implementing a trait
+        Item::Trait(item_trait) => Err(syn::Error::new_spanned(
+            item_trait.ident,
+            "#[nex_protocol] goes on the impl block of the server implementing the protocol",
+        )),
+        item => Err(syn::Error::new_spanned(
+            item,
+            "#[nex_protocol] can only be used on impl blocks",
+        )),
+    };
+
+    result
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
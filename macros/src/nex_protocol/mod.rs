@@ -0,0 +1,3 @@
+mod macro_impl;
+
+pub use macro_impl::*;
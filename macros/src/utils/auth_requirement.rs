@@ -0,0 +1,37 @@
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    Ident, Result,
+};
+
+mod kw {
+    syn::custom_keyword!(required);
+    syn::custom_keyword!(owner);
+}
+
+/// Who can call a method, parsed from `required` or `owner(argument)`.
+#[derive(Debug)]
+pub enum AuthRequirement {
+    /// Only authenticated clients.
+    Required,
+    /// Only the authenticated client whose pid is the given argument.
+    Owner(Ident),
+}
+
+impl Parse for AuthRequirement {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(kw::required) {
+            input.parse::<kw::required>()?;
+            return Ok(Self::Required);
+        }
+
+        if input.peek(kw::owner) {
+            input.parse::<kw::owner>()?;
+            let content;
+            parenthesized!(content in input);
+            return Ok(Self::Owner(content.parse()?));
+        }
+
+        Err(input.error("Expected 'required' or 'owner(argument)'"))
+    }
+}
@@ -25,6 +25,19 @@ impl EnumVariant {
             #ident::#variant
         }
     }
+
+    /// The `Route` trait for this method.
+    pub fn route_trait(&self) -> proc_macro2::TokenStream {
+        let ident = &self.ident;
+        let variant_token = self.token();
+        quote! {
+            nex_rs::route::Route::<
+                { <#ident as nex_rs::route::NexProtocol>::PROTOCOL_ID as u8 },
+                { #variant_token as u32 },
+                { <#ident as nex_rs::route::NexProtocol>::CUSTOM_ID },
+            >
+        }
+    }
 }
 
 impl Parse for EnumVariant {
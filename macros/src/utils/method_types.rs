@@ -0,0 +1,76 @@
+use quote::format_ident;
+use std::borrow::Borrow;
+use syn::{FnArg, GenericArgument, Ident, Pat, PathArguments, ReturnType, Type};
+
+/// Gets `Output` from return types like `Result<Output, Error>` or `SuccessfulResult<Output>`.
+fn get_output_type(output: &ReturnType) -> Option<&Type> {
+    let ReturnType::Type(_, output_type) = output else {
+        return None;
+    };
+    let Type::Path(type_path) = output_type.borrow() else {
+        return None;
+    };
+    let PathArguments::AngleBracketed(args) = &type_path.path.segments.last()?.arguments else {
+        return None;
+    };
+
+    match args.args.first()? {
+        GenericArgument::Type(output_type) => Some(output_type),
+        _ => None,
+    }
+}
+
+/// The values a method reads from a request and writes to its response, in order.
+pub struct MethodTypes {
+    pub inputs: Vec<Type>,
+    /// The argument names of the inputs, when they're plain identifiers.
+    pub input_names: Vec<Option<Ident>>,
+    /// Tuple outputs are written as one value after another.
+    pub output: Option<Type>,
+}
+
+impl MethodTypes {
+    /// `inputs` are the arguments after the server and client connection.
+    pub fn new<'a>(inputs: impl Iterator<Item = &'a FnArg>, output: &ReturnType) -> Self {
+        let (inputs, input_names) = inputs
+            .filter_map(|arg| match arg {
+                FnArg::Typed(arg) => {
+                    let name = match arg.pat.as_ref() {
+                        Pat::Ident(pat_ident) => Some(pat_ident.ident.clone()),
+                        _ => None,
+                    };
+                    Some((arg.ty.as_ref().clone(), name))
+                }
+                FnArg::Receiver(_) => None,
+            })
+            .unzip();
+
+        Self {
+            inputs,
+            input_names,
+            output: get_output_type(output).cloned(),
+        }
+    }
+
+    pub fn output_types(&self) -> Option<Vec<&Type>> {
+        match self.output.as_ref()? {
+            Type::Tuple(tuple) => Some(tuple.elems.iter().collect()),
+            output => Some(vec![output]),
+        }
+    }
+
+    /// Gets the identifier an input is read into by the input's argument name.
+    pub fn input_ident_by_name(&self, name: &Ident) -> Option<Ident> {
+        let index = self
+            .input_names
+            .iter()
+            .position(|input_name| input_name.as_ref() == Some(name))?;
+        Some(format_ident!("input_{}", index))
+    }
+
+    pub fn input_idents(&self) -> Vec<proc_macro2::Ident> {
+        (0..self.inputs.len())
+            .map(|index| format_ident!("input_{}", index))
+            .collect()
+    }
+}
@@ -0,0 +1,44 @@
+use quote::{quote, ToTokens};
+
+/// Runs a server's middleware around `answer`, which evaluates to what the request was answered with.
+/// A middleware rejection answers the request instead, and the middleware that ran are unwound on every path.
+pub fn get_middleware_impl(
+    server: &impl ToTokens,
+    client: &impl ToTokens,
+    request: &impl ToTokens,
+    answer: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        {
+            let middleware = nex_rs::server::Server::get_middleware(#server);
+            let middleware_run = middleware.before(#client, #request).await;
+            let answer: nex_rs::server::ServerResult<Result<(), nex_rs::nex_types::ResultCode>> =
+                match middleware_run.rejection() {
+                    Some(error_code) => nex_rs::server::Server::send_error(
+                        #server,
+                        #client,
+                        #request.protocol_id,
+                        #request.custom_id,
+                        #request.method_id,
+                        #request.call_id,
+                        error_code.into(),
+                    )
+                    .await
+                    .map(|_| Err(error_code)),
+                    None => #answer,
+                };
+
+            let outcome = match &answer {
+                Ok(outcome) => *outcome,
+                Err(_) => Err(nex_rs::nex_types::ResultCode::from(
+                    nex_rs::nex_types::Core::Exception,
+                )),
+            };
+            middleware
+                .after(middleware_run, #client, #request, outcome)
+                .await;
+
+            answer.map(|_| ())
+        }
+    }
+}
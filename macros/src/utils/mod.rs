@@ -1 +1,5 @@
+pub mod auth_requirement;
 pub mod enum_variant;
+pub mod method_types;
+pub mod middleware_impl;
+pub mod route_impl;
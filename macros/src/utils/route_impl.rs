@@ -0,0 +1,162 @@
+use super::{
+    auth_requirement::AuthRequirement, enum_variant::EnumVariant, method_types::MethodTypes,
+    middleware_impl::get_middleware_impl,
+};
+use quote::{format_ident, quote, ToTokens};
+use syn::Type;
+
+/// Implements `Route` for a server by checking the method's auth requirement, reading the method's inputs,
+/// calling the method and responding with its outputs or error code.
+/// `run` wraps this in the server's middleware.
+pub fn get_route_impl(
+    server: &impl ToTokens,
+    method: &EnumVariant,
+    callee: proc_macro2::TokenStream,
+    types: &MethodTypes,
+    auth: Option<&AuthRequirement>,
+) -> proc_macro2::TokenStream {
+    let route_trait = method.route_trait();
+    let input_read_error = format!("Cannot read {} input", method);
+    let input_idents = types.input_idents();
+    let input_types = &types.inputs;
+
+    let reject = quote! {
+        nex_rs::server::Server::send_error(
+            self,
+            client,
+            request.protocol_id,
+            request.custom_id,
+            request.method_id,
+            request.call_id,
+            error_code.into(),
+        )
+        .await?;
+        return Ok(Err(error_code));
+    };
+
+    let authentication_check = match auth {
+        Some(_) => quote! {
+            if let Err(error_code) = nex_rs::client::ClientConnection::require_authentication(client) {
+                #reject
+            }
+        },
+        None => quote!(),
+    };
+
+    // Ownership is checked against an input, so it can only happen once inputs are read
+    let owner_check = match auth {
+        Some(AuthRequirement::Owner(owner)) => match types.input_ident_by_name(owner) {
+            Some(owner_ident) => quote! {
+                if let Err(error_code) = nex_rs::client::ClientConnection::require_owner(client, #owner_ident) {
+                    #reject
+                }
+            },
+            None => {
+                return syn::Error::new_spanned(owner, "Cannot find an input with this name")
+                    .to_compile_error()
+            }
+        },
+        _ => quote!(),
+    };
+
+    let input_read = if input_idents.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            let parameters = request.parameters.as_slice();
+            let mut parameters_stream = no_std_io::StreamContainer::new(parameters);
+            #(
+                let #input_idents = match no_std_io::StreamReader::read_stream_le::<#input_types>(&mut parameters_stream) {
+                    Ok(input) => input,
+                    Err(_) => {
+                        nex_rs::server::EventHandler::on_error(self, &nex_rs::result::Error::from(#input_read_error)).await;
+                        let error_code = nex_rs::nex_types::ResultCode::from(nex_rs::nex_types::Core::InvalidArgument);
+                        #reject
+                    }
+                };
+            )*
+        }
+    };
+
+    // Each value of a tuple output is written after the previous one
+    let response_write = match &types.output {
+        Some(Type::Tuple(tuple)) => {
+            let output_idents: Vec<_> = (0..tuple.elems.len())
+                .map(|index| format_ident!("output_{}", index))
+                .collect();
+            quote! {
+                let (#(#output_idents,)*) = response;
+                #(no_std_io::StreamWriter::write_stream_le(&mut response_stream, &#output_idents)?;)*
+            }
+        }
+        _ => quote! {
+            no_std_io::StreamWriter::write_stream_le(&mut response_stream, &response)?;
+        },
+    };
+
+    let routed_answer = get_middleware_impl(
+        &quote!(self),
+        &quote!(client),
+        &quote!(request),
+        quote!(#route_trait::respond(self, client, request).await),
+    );
+
+    quote! {
+        #[async_trait::async_trait]
+        impl #route_trait for #server {
+            async fn respond(
+                &self,
+                client: &mut nex_rs::client::ClientConnection,
+                request: &nex_rs::rmc::RMCRequest,
+            ) -> nex_rs::server::ServerResult<Result<(), nex_rs::nex_types::ResultCode>> {
+                #authentication_check
+
+                #input_read
+
+                #owner_check
+
+                match #callee(self, client, #(#input_idents),*).await {
+                    Ok(response) => {
+                        #[allow(unused_mut)]
+                        let mut response_stream = no_std_io::StreamContainer::new(vec![]);
+                        #response_write
+                        nex_rs::server::Server::send_success(
+                            self,
+                            client,
+                            request.protocol_id,
+                            request.custom_id,
+                            request.method_id,
+                            request.call_id,
+                            response_stream.into_raw(),
+                        )
+                        .await?;
+                        Ok(Ok(()))
+                    }
+                    Err(error) => {
+                        let error_code = nex_rs::result::NexError::error_code(&error);
+                        nex_rs::server::EventHandler::on_error(self, &error.into()).await;
+                        nex_rs::server::Server::send_error(
+                            self,
+                            client,
+                            request.protocol_id,
+                            request.custom_id,
+                            request.method_id,
+                            request.call_id,
+                            error_code.into(),
+                        )
+                        .await?;
+                        Ok(Err(error_code))
+                    }
+                }
+            }
+
+            async fn run(
+                &self,
+                client: &mut nex_rs::client::ClientConnection,
+                request: &nex_rs::rmc::RMCRequest,
+            ) -> nex_rs::server::ServerResult<()> {
+                #routed_answer
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+#![allow(dead_code)]
+
+use nex_rs::{
+    client::{ClientConnection, ClientContext},
+    packet::PacketV1,
+    rmc::RMCRequest,
+    server::{BaseServer, EventHandler, Server, ServerResult},
+};
+use std::sync::Mutex;
+
+#[derive(Default)]
+pub struct MockServer {
+    base: BaseServer,
+    pub response_custom_id: Mutex<Option<u16>>,
+    pub response_data: Mutex<Option<Vec<u8>>>,
+    pub response_error_code: Mutex<Option<u32>>,
+    pub unrouted_method_ids: Mutex<Vec<u32>>,
+    pub protocol_methods: Mutex<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl EventHandler for MockServer {
+    async fn on_syn(&self, _client: &mut ClientConnection, _packet: &PacketV1) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_connect(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_data(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_disconnect(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_ping(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_rmc_request(
+        &self,
+        _client: &mut ClientConnection,
+        _rmc_request: &RMCRequest,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_unrouted_method(&self, _client: &mut ClientConnection, rmc_request: &RMCRequest) {
+        self.unrouted_method_ids
+            .lock()
+            .unwrap()
+            .push(rmc_request.method_id);
+    }
+    async fn on_protocol_method(&self, method_name: String) {
+        self.protocol_methods.lock().unwrap().push(method_name);
+    }
+    async fn on_error(&self, _error: &nex_rs::result::Error) {}
+}
+
+#[async_trait::async_trait]
+impl Server for MockServer {
+    fn get_base(&self) -> &BaseServer {
+        &self.base
+    }
+
+    fn get_mut_base(&mut self) -> &mut BaseServer {
+        &mut self.base
+    }
+
+    async fn send_success<MethodId: Into<u32> + Send, Data: Into<Vec<u8>> + Send>(
+        &self,
+        _client: &mut ClientConnection,
+        _protocol_id: u8,
+        custom_id: u16,
+        _method_id: MethodId,
+        _call_id: u32,
+        data: Data,
+    ) -> ServerResult<()> {
+        *self.response_custom_id.lock().unwrap() = Some(custom_id);
+        *self.response_data.lock().unwrap() = Some(data.into());
+        Ok(())
+    }
+
+    async fn send_error<MethodId: Into<u32> + Send>(
+        &self,
+        _client: &mut ClientConnection,
+        _protocol_id: u8,
+        custom_id: u16,
+        _method_id: MethodId,
+        _call_id: u32,
+        error_code: u32,
+    ) -> ServerResult<()> {
+        *self.response_custom_id.lock().unwrap() = Some(custom_id);
+        *self.response_error_code.lock().unwrap() = Some(error_code);
+        Ok(())
+    }
+}
+
+pub fn get_server_and_client() -> (MockServer, ClientConnection) {
+    // Set up server
+    let server = MockServer::default();
+
+    // Set up client
+    let addr = "127.0.0.1:12345".parse().unwrap();
+    let context = ClientContext::new(0, "");
+    let client = ClientConnection::new(addr, context, 0);
+
+    (server, client)
+}
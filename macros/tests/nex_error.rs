@@ -0,0 +1,85 @@
+use macros::NexError;
+use nex_rs::{
+    nex_types::{Core, RendezVous, ResultCode},
+    result::NexError,
+    snafu::{ensure, Error, OptionExt, ResultExt},
+};
+
+#[derive(Debug, PartialEq, NexError)]
+pub enum ParseError {
+    #[nex_error(code = Core::InvalidArgument, display("Invalid digit {}", digit))]
+    InvalidDigit { digit: char },
+}
+
+#[derive(Debug, NexError)]
+pub enum LoginError {
+    #[nex_error(code = RendezVous::InvalidUsername, display("Unknown user {username}"))]
+    UnknownUser { username: String },
+    #[nex_error(code = RendezVous::InvalidPassword)]
+    WrongPassword,
+    #[nex_error(code = 0x80010001, display("Invalid pid: {}", source))]
+    InvalidPid { source: ParseError },
+}
+
+fn parse_pid(pid: &str) -> Result<u32, ParseError> {
+    pid.chars().try_fold(0, |pid, digit| {
+        let digit = digit.to_digit(10).context(InvalidDigit { digit })?;
+        Ok(pid * 10 + digit)
+    })
+}
+
+fn login(username: &str, password: &str) -> Result<u32, LoginError> {
+    let pid = [("1234", "hunter2"), ("12a4", "password")]
+        .iter()
+        .find(|(pid, _)| *pid == username)
+        .map(|(pid, stored_password)| (*pid, *stored_password));
+
+    let (pid, stored_password) = pid.context(UnknownUser { username })?;
+    ensure!(password == stored_password, WrongPassword);
+
+    parse_pid(pid).context(InvalidPid)
+}
+
+#[test]
+fn maps_variants_to_result_codes() {
+    let error = login("5678", "").unwrap_err();
+    assert_eq!(error.error_code(), RendezVous::InvalidUsername);
+
+    let error = login("1234", "").unwrap_err();
+    assert_eq!(error.error_code(), RendezVous::InvalidPassword);
+
+    let error = login("12a4", "password").unwrap_err();
+    assert_eq!(error.error_code(), ResultCode::from(0x80010001));
+}
+
+#[test]
+fn displays_variants() {
+    assert_eq!(
+        login("5678", "").unwrap_err().to_string(),
+        "Unknown user 5678"
+    );
+    assert_eq!(login("1234", "").unwrap_err().to_string(), "WrongPassword");
+    assert_eq!(
+        login("12a4", "password").unwrap_err().to_string(),
+        "Invalid pid: Invalid digit a"
+    );
+}
+
+#[test]
+fn keeps_sources() {
+    let error = login("12a4", "password").unwrap_err();
+    assert_eq!(
+        error.source().map(|source| source.to_string()),
+        Some("Invalid digit a".to_string())
+    );
+    assert!(login("1234", "").unwrap_err().source().is_none());
+}
+
+#[test]
+fn builds_errors_with_selectors() {
+    assert_eq!(login("1234", "hunter2").ok(), Some(1234));
+    assert_eq!(
+        InvalidDigit { digit: 'x' }.fail::<()>(),
+        Err(ParseError::InvalidDigit { digit: 'x' })
+    );
+}
@@ -1,15 +1,18 @@
-use macros::{nex_method, nex_route};
+mod common;
+
+use common::{get_server_and_client, MockServer};
+use macros::{match_nex_route, nex_method, nex_route, NexError};
 use nex_rs::{
-    client::{ClientConnection, ClientContext},
-    nex_types::Empty,
-    packet::PacketV1,
-    result::SuccessfulResult,
-    rmc::RMCRequest,
-    route::{NexProtocol, Route},
-    server::{BaseServer, EventHandler, Server, ServerResult},
+    client::ClientConnection,
+    nex_types::{Core, Empty, NexList, ResultCode},
+    result::{self, SuccessfulResult},
+    rmc::{RMCCaller, RMCRequest, RMCResponse},
+    route::{NexProtocol, Route, EXTENDED_PROTOCOL_ID},
+    server::{Middleware, Server, ServerResult},
 };
 use no_std_io::{EndianRead, EndianWrite, Writer};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Default, EndianRead, EndianWrite)]
 pub struct AddInput {
@@ -27,13 +30,64 @@ pub struct AddOutput {
 enum MathMethod {
     Add = 1,
     Noop = 2,
+    Divide = 3,
 }
 
 impl NexProtocol for MathMethod {
     const PROTOCOL_ID: u8 = 1;
 }
 
-#[nex_method(method = MathMethod::Add)]
+#[derive(Debug, IntoPrimitive, TryFromPrimitive)]
+#[repr(u32)]
+enum ExtendedMethod {
+    Ping = 1,
+}
+
+impl NexProtocol for ExtendedMethod {
+    const PROTOCOL_ID: u8 = EXTENDED_PROTOCOL_ID;
+    const CUSTOM_ID: u16 = 0x1234;
+}
+
+#[derive(Debug, IntoPrimitive, TryFromPrimitive)]
+#[repr(u32)]
+enum SessionMethod {
+    Login = 1,
+    GetStatus = 2,
+    UpdateStatus = 3,
+}
+
+impl NexProtocol for SessionMethod {
+    const PROTOCOL_ID: u8 = 3;
+}
+
+#[nex_method(method = SessionMethod::Login, client = call_login)]
+async fn login(
+    _server: &MockServer,
+    client: &mut ClientConnection,
+    pid: u32,
+    friend_pids: NexList<u32>,
+) -> SuccessfulResult<(u32, NexList<u32>)> {
+    client.set_pid(pid);
+    let friend_pids = Vec::from(friend_pids);
+    Ok((friend_pids.len() as u32, friend_pids.into()))
+}
+
+#[nex_method(method = SessionMethod::GetStatus, auth = required)]
+async fn get_status(_server: &MockServer, _client: &ClientConnection) -> SuccessfulResult<u32> {
+    Ok(1)
+}
+
+#[nex_method(method = SessionMethod::UpdateStatus, auth = owner(pid))]
+async fn update_status(
+    _server: &MockServer,
+    _client: &ClientConnection,
+    pid: u32,
+    status: u32,
+) -> SuccessfulResult<u32> {
+    Ok(pid + status)
+}
+
+#[nex_method(method = MathMethod::Add, client = call_add)]
 async fn add(
     _server: &MockServer,
     _client: &ClientConnection,
@@ -44,105 +98,98 @@ async fn add(
     })
 }
 
+#[derive(Debug, Default, EndianRead, EndianWrite)]
+pub struct DivideInput {
+    dividend: u32,
+    divisor: u32,
+}
+
+#[derive(Debug, NexError)]
+pub enum DivideError {
+    #[nex_error(code = Core::InvalidArgument, display("Cannot divide {} by zero", dividend))]
+    DivideByZero { dividend: u32 },
+}
+
+#[nex_method(method = MathMethod::Divide, client = call_divide)]
+async fn divide(
+    _server: &MockServer,
+    _client: &ClientConnection,
+    input: DivideInput,
+) -> Result<u32, DivideError> {
+    input
+        .dividend
+        .checked_div(input.divisor)
+        .ok_or(DivideError::DivideByZero {
+            dividend: input.dividend,
+        })
+}
+
 #[nex_method(method = MathMethod::Noop)]
 async fn noop(_server: &MockServer, _client: &ClientConnection) -> SuccessfulResult<Empty> {
     Ok(Empty)
 }
 
-#[derive(Default)]
-struct MockServer {
-    base: BaseServer,
+#[nex_method(method = ExtendedMethod::Ping)]
+async fn ping(_server: &MockServer, _client: &ClientConnection) -> SuccessfulResult<Empty> {
+    Ok(Empty)
 }
 
-#[async_trait::async_trait]
-impl EventHandler for MockServer {
-    async fn on_syn(&self, _client: &mut ClientConnection, _packet: &PacketV1) -> ServerResult<()> {
-        Ok(())
-    }
-    async fn on_connect(
-        &self,
-        _client: &mut ClientConnection,
-        _packet: &PacketV1,
-    ) -> ServerResult<()> {
-        Ok(())
-    }
-    async fn on_data(
-        &self,
-        _client: &mut ClientConnection,
-        _packet: &PacketV1,
-    ) -> ServerResult<()> {
-        Ok(())
-    }
-    async fn on_disconnect(
-        &self,
-        _client: &mut ClientConnection,
-        _packet: &PacketV1,
-    ) -> ServerResult<()> {
-        Ok(())
-    }
-    async fn on_ping(
-        &self,
-        _client: &mut ClientConnection,
-        _packet: &PacketV1,
-    ) -> ServerResult<()> {
-        Ok(())
-    }
-    async fn on_rmc_request(
-        &self,
-        _client: &mut ClientConnection,
-        _rmc_request: &RMCRequest,
-    ) -> ServerResult<()> {
-        Ok(())
-    }
-    async fn on_protocol_method(&self, _method_name: String) {}
-    async fn on_error(&self, _error: &nex_rs::result::Error) {}
+/// Rejects requests with its result code, if it has one, and records outcomes.
+struct MockMiddleware {
+    rejection: Option<ResultCode>,
+    outcomes: Arc<Mutex<Vec<Result<(), ResultCode>>>>,
 }
 
 #[async_trait::async_trait]
-impl Server for MockServer {
-    fn get_base(&self) -> &BaseServer {
-        &self.base
-    }
-
-    fn get_mut_base(&mut self) -> &mut BaseServer {
-        &mut self.base
-    }
-
-    async fn send_success<MethodId: Into<u32> + Send, Data: Into<Vec<u8>> + Send>(
+impl Middleware for MockMiddleware {
+    async fn before(
         &self,
         _client: &mut ClientConnection,
-        _protocol_id: u8,
-        _method_id: MethodId,
-        _call_id: u32,
-        _data: Data,
-    ) -> ServerResult<()> {
-        // stub
-        Ok(())
+        _request: &RMCRequest,
+    ) -> Result<(), ResultCode> {
+        self.rejection.map_or(Ok(()), Err)
     }
 
-    async fn send_error<MethodId: Into<u32> + Send>(
+    async fn after(
         &self,
         _client: &mut ClientConnection,
-        _protocol_id: u8,
-        _method_id: MethodId,
-        _call_id: u32,
-        _error_code: u32,
-    ) -> ServerResult<()> {
-        // stub
-        Ok(())
+        _request: &RMCRequest,
+        outcome: Result<(), ResultCode>,
+    ) {
+        self.outcomes.lock().unwrap().push(outcome);
     }
 }
 
-fn get_server_and_client() -> (MockServer, ClientConnection) {
-    // Set up server
-    let server = MockServer::default();
+/// Answers every call with the same response.
+struct MockCaller {
+    response: Result<Vec<u8>, u32>,
+    requests: Vec<RMCRequest>,
+}
 
-    // Set up client
-    let addr = "127.0.0.1:12345".parse().unwrap();
-    let context = ClientContext::new(0, "");
-    let client = ClientConnection::new(addr, context, 0);
+#[async_trait::async_trait]
+impl RMCCaller for MockCaller {
+    fn next_call_id(&mut self) -> u32 {
+        self.requests.len() as u32 + 1
+    }
 
-    (server, client)
+    async fn call(&mut self, request: RMCRequest) -> Result<RMCResponse, result::Error> {
+        let response = match &self.response {
+            Ok(data) => RMCResponse::new_success(
+                request.protocol_id,
+                request.method_id,
+                request.call_id,
+                data.clone(),
+            ),
+            Err(error_code) => RMCResponse::new_error(
+                request.protocol_id,
+                request.method_id,
+                request.call_id,
+                *error_code,
+            ),
+        };
+        self.requests.push(request);
+        Ok(response)
+    }
 }
 
 #[tokio::test]
@@ -187,3 +234,400 @@ async fn routes_method() {
 
     assert_eq!(result, Ok(()))
 }
+
+#[tokio::test]
+async fn routes_extended_protocol_methods() {
+    let (server, mut client) = get_server_and_client();
+
+    let request = RMCRequest {
+        protocol_id: EXTENDED_PROTOCOL_ID,
+        call_id: 1,
+        method_id: 1,
+        custom_id: 0x1234,
+        parameters: vec![],
+    };
+
+    assert!(request.is_method(ExtendedMethod::Ping));
+    assert!(!request.is_method(MathMethod::Add));
+
+    let result = nex_route![ExtendedMethod::Ping](&server, &mut client, &request).await;
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(*server.response_custom_id.lock().unwrap(), Some(0x1234));
+}
+
+#[test]
+fn does_not_match_other_custom_ids() {
+    let request = RMCRequest {
+        protocol_id: EXTENDED_PROTOCOL_ID,
+        call_id: 1,
+        method_id: 1,
+        custom_id: 0x4321,
+        parameters: vec![],
+    };
+
+    assert!(!request.is_method(ExtendedMethod::Ping));
+}
+
+async fn route_request(
+    server: &MockServer,
+    client: &mut ClientConnection,
+    request: &RMCRequest,
+) -> ServerResult<()> {
+    match_nex_route!(
+        server,
+        client,
+        request,
+        exhaustive,
+        MathMethod::Add,
+        MathMethod::Noop,
+        MathMethod::Divide
+    )
+}
+
+#[tokio::test]
+async fn matches_routed_methods() {
+    let (server, mut client) = get_server_and_client();
+
+    let request = RMCRequest {
+        protocol_id: 1,
+        call_id: 1,
+        method_id: 2,
+        custom_id: 0,
+        parameters: vec![],
+    };
+
+    let result = route_request(&server, &mut client, &request).await;
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(*server.response_error_code.lock().unwrap(), None);
+    assert!(server.unrouted_method_ids.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn responds_not_implemented_to_unrouted_methods() {
+    let (server, mut client) = get_server_and_client();
+
+    let request = RMCRequest {
+        protocol_id: 1,
+        call_id: 1,
+        method_id: 4,
+        custom_id: 0,
+        parameters: vec![],
+    };
+
+    let result = route_request(&server, &mut client, &request).await;
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x80010002)
+    );
+    assert_eq!(*server.unrouted_method_ids.lock().unwrap(), vec![4]);
+}
+
+#[tokio::test]
+async fn responds_with_derived_error_codes() {
+    let (server, mut client) = get_server_and_client();
+    let mut input = vec![];
+    input.checked_write_le(
+        0,
+        &DivideInput {
+            dividend: 1,
+            divisor: 0,
+        },
+    );
+
+    let request = RMCRequest {
+        protocol_id: 1,
+        call_id: 1,
+        method_id: 3,
+        custom_id: 0,
+        parameters: input,
+    };
+
+    let result = route_request(&server, &mut client, &request).await;
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x8001000A)
+    );
+}
+
+#[tokio::test]
+async fn generates_client_calls() {
+    let mut output = vec![];
+    output.checked_write_le(0, &AddOutput { sum: 3 });
+    let mut caller = MockCaller {
+        response: Ok(output),
+        requests: vec![],
+    };
+
+    let result = call_add(
+        &mut caller,
+        AddInput {
+            first: 1,
+            second: 2,
+        },
+    )
+    .await;
+
+    assert_eq!(result, Ok(Ok(AddOutput { sum: 3 })));
+    assert_eq!(caller.requests.len(), 1);
+    assert_eq!(caller.requests[0].protocol_id, 1);
+    assert_eq!(caller.requests[0].method_id, 1);
+    assert_eq!(caller.requests[0].call_id, 1);
+    assert_eq!(caller.requests[0].parameters, vec![1, 0, 0, 0, 2, 0, 0, 0]);
+}
+
+/// Answers every call as if it were a different method.
+struct OtherMethodCaller;
+
+#[async_trait::async_trait]
+impl RMCCaller for OtherMethodCaller {
+    fn next_call_id(&mut self) -> u32 {
+        1
+    }
+
+    async fn call(&mut self, request: RMCRequest) -> Result<RMCResponse, result::Error> {
+        Ok(RMCResponse::new_success(
+            request.protocol_id,
+            request.method_id + 1,
+            request.call_id,
+            vec![3, 0, 0, 0],
+        ))
+    }
+}
+
+#[tokio::test]
+async fn rejects_responses_to_other_methods() {
+    let result = call_add(
+        &mut OtherMethodCaller,
+        AddInput {
+            first: 1,
+            second: 2,
+        },
+    )
+    .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn returns_result_codes_from_client_calls() {
+    let mut caller = MockCaller {
+        response: Err(0x8001000A),
+        requests: vec![],
+    };
+
+    let result = call_divide(
+        &mut caller,
+        DivideInput {
+            dividend: 1,
+            divisor: 0,
+        },
+    )
+    .await;
+
+    assert_eq!(result, Ok(Err(ResultCode::from(Core::InvalidArgument))));
+}
+
+#[tokio::test]
+async fn reads_multiple_inputs_and_writes_tuple_outputs() {
+    let (server, mut client) = get_server_and_client();
+    let mut input = vec![];
+    input.checked_write_le(0, &1234u32);
+    input.checked_write_le(4, &NexList::from(vec![5u32, 6]));
+
+    let request = RMCRequest {
+        protocol_id: 3,
+        call_id: 1,
+        method_id: 1,
+        custom_id: 0,
+        parameters: input,
+    };
+
+    let result = nex_route![SessionMethod::Login](&server, &mut client, &request).await;
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(client.get_pid(), 1234);
+    assert_eq!(
+        *server.response_data.lock().unwrap(),
+        Some(vec![2, 0, 0, 0, 2, 0, 0, 0, 5, 0, 0, 0, 6, 0, 0, 0])
+    );
+}
+
+#[tokio::test]
+async fn generates_client_calls_with_multiple_inputs_and_outputs() {
+    let mut caller = MockCaller {
+        response: Ok(vec![1, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0]),
+        requests: vec![],
+    };
+
+    let (count, friend_pids) = call_login(&mut caller, 1234, vec![5].into())
+        .await
+        .expect("Should have succeeded!")
+        .expect("Should have succeeded!");
+
+    assert_eq!(count, 1);
+    assert_eq!(Vec::from(friend_pids), vec![5]);
+    assert_eq!(
+        caller.requests[0].parameters,
+        vec![0xd2, 0x04, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0]
+    );
+}
+
+#[tokio::test]
+async fn runs_middleware_around_methods() {
+    let (mut server, mut client) = get_server_and_client();
+    let outcomes = Arc::new(Mutex::new(vec![]));
+    server.get_mut_middleware().add(MockMiddleware {
+        rejection: None,
+        outcomes: outcomes.clone(),
+    });
+    server.get_mut_middleware().add_for_method(
+        MathMethod::Noop,
+        MockMiddleware {
+            rejection: Some(Core::AccessDenied.into()),
+            outcomes: Arc::new(Mutex::new(vec![])),
+        },
+    );
+
+    let mut input = vec![];
+    input.checked_write_le(
+        0,
+        &DivideInput {
+            dividend: 1,
+            divisor: 0,
+        },
+    );
+    let divide = RMCRequest {
+        protocol_id: 1,
+        call_id: 1,
+        method_id: 3,
+        custom_id: 0,
+        parameters: input,
+    };
+    let noop = RMCRequest {
+        protocol_id: 1,
+        call_id: 2,
+        method_id: 2,
+        custom_id: 0,
+        parameters: vec![],
+    };
+
+    let result = route_request(&server, &mut client, &divide).await;
+    assert_eq!(result, Ok(()));
+    let result = route_request(&server, &mut client, &noop).await;
+    assert_eq!(result, Ok(()));
+
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x80010006)
+    );
+    assert_eq!(
+        *outcomes.lock().unwrap(),
+        vec![
+            Err(ResultCode::from(Core::InvalidArgument)),
+            Err(ResultCode::from(Core::AccessDenied))
+        ]
+    );
+}
+
+#[tokio::test]
+async fn runs_middleware_around_unrouted_and_unreadable_requests() {
+    let (mut server, mut client) = get_server_and_client();
+    let outcomes = Arc::new(Mutex::new(vec![]));
+    server.get_mut_middleware().add(MockMiddleware {
+        rejection: None,
+        outcomes: outcomes.clone(),
+    });
+
+    let unreadable_divide = RMCRequest {
+        protocol_id: 1,
+        call_id: 1,
+        method_id: 3,
+        custom_id: 0,
+        parameters: vec![],
+    };
+    let unrouted = RMCRequest {
+        protocol_id: 1,
+        call_id: 2,
+        method_id: 9,
+        custom_id: 0,
+        parameters: vec![],
+    };
+
+    let result = route_request(&server, &mut client, &unreadable_divide).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x8001000a)
+    );
+
+    let result = route_request(&server, &mut client, &unrouted).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(*server.unrouted_method_ids.lock().unwrap(), vec![9]);
+
+    assert_eq!(
+        *outcomes.lock().unwrap(),
+        vec![
+            Err(ResultCode::from(Core::InvalidArgument)),
+            Err(ResultCode::from(Core::NotImplemented))
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rejects_unauthenticated_clients() {
+    let (server, mut client) = get_server_and_client();
+    let request = RMCRequest {
+        protocol_id: 3,
+        call_id: 1,
+        method_id: 2,
+        custom_id: 0,
+        parameters: vec![],
+    };
+
+    let result = nex_route![SessionMethod::GetStatus](&server, &mut client, &request).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x80030002)
+    );
+    assert_eq!(*server.response_data.lock().unwrap(), None);
+
+    client.authenticate(1234);
+    let result = nex_route![SessionMethod::GetStatus](&server, &mut client, &request).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_data.lock().unwrap(),
+        Some(vec![1, 0, 0, 0])
+    );
+}
+
+#[tokio::test]
+async fn rejects_clients_that_do_not_own_the_input() {
+    let (server, mut client) = get_server_and_client();
+    client.authenticate(1234);
+
+    let mut input = vec![];
+    input.checked_write_le(0, &5678u32);
+    input.checked_write_le(4, &1u32);
+    let request = RMCRequest {
+        protocol_id: 3,
+        call_id: 1,
+        method_id: 3,
+        custom_id: 0,
+        parameters: input,
+    };
+
+    let result = nex_route![SessionMethod::UpdateStatus](&server, &mut client, &request).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x800300d9)
+    );
+    assert_eq!(*server.response_data.lock().unwrap(), None);
+}
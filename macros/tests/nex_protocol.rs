@@ -0,0 +1,146 @@
+mod common;
+
+use common::{get_server_and_client, MockServer};
+use macros::nex_protocol;
+use nex_rs::{
+    client::ClientConnection,
+    nex_types::Empty,
+    result::SuccessfulResult,
+    rmc::RMCRequest,
+    route::{Dispatch, NexProtocol},
+};
+use no_std_io::{EndianRead, EndianWrite, Writer};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+#[derive(Debug, Clone, Copy, IntoPrimitive, TryFromPrimitive)]
+#[repr(u32)]
+enum CounterMethod {
+    Increment = 1,
+    GetPID = 2,
+    Reset = 3,
+    SetOwnCount = 4,
+}
+
+impl NexProtocol for CounterMethod {
+    const PROTOCOL_ID: u8 = 2;
+}
+
+#[derive(Debug, Default, EndianRead, EndianWrite)]
+pub struct IncrementInput {
+    value: u32,
+}
+
+#[nex_protocol(CounterMethod)]
+impl MockServer {
+    async fn increment(
+        &self,
+        _client: &ClientConnection,
+        input: IncrementInput,
+    ) -> SuccessfulResult<u32> {
+        Ok(self.add_one(input.value).await)
+    }
+
+    #[nex_protocol(skip)]
+    async fn add_one(&self, value: u32) -> u32 {
+        value + 1
+    }
+
+    #[method(GetPID)]
+    async fn get_pid(&self, client: &ClientConnection) -> SuccessfulResult<u32> {
+        Ok(client.get_pid())
+    }
+
+    #[auth(owner(pid))]
+    async fn set_own_count(
+        &self,
+        _client: &ClientConnection,
+        pid: u32,
+        count: u32,
+    ) -> SuccessfulResult<u32> {
+        Ok(pid + count)
+    }
+
+    fn helper(&self) {}
+}
+
+fn new_request(method_id: u32, parameters: Vec<u8>) -> RMCRequest {
+    RMCRequest {
+        protocol_id: CounterMethod::PROTOCOL_ID,
+        call_id: 1,
+        method_id,
+        custom_id: 0,
+        parameters,
+    }
+}
+
+#[tokio::test]
+async fn dispatches_methods() {
+    let (server, mut client) = get_server_and_client();
+    let mut input = vec![];
+    input.checked_write_le(0, &IncrementInput { value: 1 });
+
+    let increment = new_request(CounterMethod::Increment.into(), input);
+    let get_pid = new_request(CounterMethod::GetPID.into(), vec![]);
+
+    let result = Dispatch::<CounterMethod>::dispatch(&server, &mut client, &increment).await;
+    assert_eq!(result, Ok(()));
+    let result = Dispatch::<CounterMethod>::dispatch(&server, &mut client, &get_pid).await;
+    assert_eq!(result, Ok(()));
+
+    assert_eq!(
+        *server.protocol_methods.lock().unwrap(),
+        vec!["CounterMethod::Increment", "CounterMethod::GetPID"]
+    );
+    assert_eq!(*server.response_error_code.lock().unwrap(), None);
+    server.helper();
+}
+
+#[tokio::test]
+async fn responds_not_implemented_to_missing_methods() {
+    let (server, mut client) = get_server_and_client();
+    let reset = new_request(CounterMethod::Reset.into(), vec![]);
+
+    let result = Dispatch::<CounterMethod>::dispatch(&server, &mut client, &reset).await;
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x80010002)
+    );
+    assert_eq!(*server.unrouted_method_ids.lock().unwrap(), vec![3]);
+    assert!(server.protocol_methods.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn checks_auth_attributes() {
+    let (server, mut client) = get_server_and_client();
+    let mut input = vec![];
+    input.checked_write_le(0, &1234u32);
+    input.checked_write_le(4, &5u32);
+    let set_own_count = new_request(CounterMethod::SetOwnCount.into(), input);
+
+    let result = Dispatch::<CounterMethod>::dispatch(&server, &mut client, &set_own_count).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x80030002)
+    );
+
+    client.authenticate(5678);
+    let result = Dispatch::<CounterMethod>::dispatch(&server, &mut client, &set_own_count).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        *server.response_error_code.lock().unwrap(),
+        Some(0x800300d9)
+    );
+
+    *server.response_error_code.lock().unwrap() = None;
+    client.authenticate(1234);
+    let result = Dispatch::<CounterMethod>::dispatch(&server, &mut client, &set_own_count).await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(*server.response_error_code.lock().unwrap(), None);
+    assert_eq!(
+        *server.response_data.lock().unwrap(),
+        Some(1239u32.to_le_bytes().to_vec())
+    );
+}
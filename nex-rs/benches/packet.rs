@@ -0,0 +1,121 @@
+use bytes::BytesMut;
+use criterion::{black_box, criterion_group, BatchSize, Criterion};
+use nex_rs::{
+    crypto::rc4::Rc4,
+    packet::{Packet, PacketV1, SignatureContext},
+    rmc::RMCRequest,
+};
+use no_std_io::Reader;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const FLAGS_VERSION: u32 = 1;
+const ACCESS_KEY: &str = "ridfebb9";
+
+/// Counts allocations so each benchmark can report how many it makes per operation.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations<T>(operation: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(operation());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn new_cipher() -> Rc4 {
+    Rc4::new(b"CD&ML")
+}
+
+fn rmc_payload() -> Vec<u8> {
+    RMCRequest::new(0x0a, 1u32, 1, vec![0xaa; 512])
+        .try_into()
+        .expect("Should have succeeded!")
+}
+
+/// An encoded data packet with an encrypted RMC request, as a client would send it.
+fn encoded_packet(context: &SignatureContext) -> Vec<u8> {
+    let mut payload = rmc_payload();
+    new_cipher().apply_in_place(&mut payload);
+
+    let packet = PacketV1::new_data_packet(1, vec![0; 16], payload, FLAGS_VERSION);
+    packet.to_bytes(context).expect("Should have succeeded!")
+}
+
+fn read_packet(bytes: &[u8]) -> PacketV1 {
+    PacketV1::read_packet(BytesMut::from(bytes), FLAGS_VERSION).expect("Should have succeeded!")
+}
+
+fn report_allocations() {
+    let context = SignatureContext::new(ACCESS_KEY);
+    let bytes = encoded_packet(&context);
+    let packet = read_packet(&bytes);
+    let payload = rmc_payload();
+
+    let read = count_allocations(|| read_packet(&bytes));
+    let validate = count_allocations(|| packet.validate(&context));
+    let mut encrypted = packet.get_payload().to_vec();
+    let decrypt = count_allocations(|| new_cipher().apply_in_place(&mut encrypted));
+    let rmc_decode = count_allocations(|| payload.read_le::<RMCRequest>(0));
+
+    eprintln!("allocations per operation:");
+    eprintln!("  read_packet: {}", read);
+    eprintln!("  validate: {}", validate);
+    eprintln!("  decrypt: {}", decrypt);
+    eprintln!("  rmc_decode: {}", rmc_decode);
+}
+
+fn packet_benchmarks(c: &mut Criterion) {
+    let context = SignatureContext::new(ACCESS_KEY);
+    let bytes = encoded_packet(&context);
+    let packet = read_packet(&bytes);
+    let payload = rmc_payload();
+    let cipher = new_cipher();
+
+    c.bench_function("read_packet", |b| b.iter(|| read_packet(black_box(&bytes))));
+
+    c.bench_function("validate", |b| {
+        b.iter(|| black_box(&packet).validate(&context))
+    });
+
+    c.bench_function("decrypt", |b| {
+        b.iter_batched_ref(
+            || (cipher.clone(), packet.get_payload().to_vec()),
+            |(cipher, payload)| cipher.apply_in_place(payload),
+            BatchSize::SmallInput,
+        )
+    });
+
+    c.bench_function("rmc_decode", |b| {
+        b.iter(|| black_box(payload.as_slice()).read_le::<RMCRequest>(0))
+    });
+}
+
+criterion_group!(benches, packet_benchmarks);
+
+fn main() {
+    report_allocations();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
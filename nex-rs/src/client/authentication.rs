@@ -0,0 +1,9 @@
+/// Whether a client has proven who it is, such as with a valid Kerberos ticket.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AuthenticationState {
+    #[default]
+    Unauthenticated,
+    Authenticated {
+        pid: u32,
+    },
+}
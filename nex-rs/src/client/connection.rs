@@ -1,172 +1,787 @@
-use super::{ClientConnectionResult, ClientContext, Error};
+use super::{
+    registry::ClientIndexes, AuthenticationState, ClientConnectionResult, ClientContext, Error,
+    SendQueue, SendQueuePoll,
+};
 use crate::{
+    counter::Counter,
     crypto::rc4::Rc4,
-    packet::{Packet, PacketResult, PacketV1},
+    nex_types::{RendezVous, ResultCode},
+    packet::{split_fragments, Packet, PacketResult, PacketV1},
     rmc::{RMCRequest, RMCResponse},
+    route::NexProtocol,
 };
 use no_std_io::Reader;
-use std::net::SocketAddr;
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::Instant,
+};
+use tokio::sync::{Mutex as AsyncMutex, Notify, OwnedMutexGuard};
 
-#[derive(Clone)]
-pub struct ClientConnection {
+struct ConnectionState {
     address: SocketAddr,
+    connection_id: u32,
     session_id: u8,
     pid: u32,
+    authentication: AuthenticationState,
     is_connected: bool,
     kick_timer: u32,
+    fragment_size: Option<u16>,
     context: ClientContext,
+    call_id_out: Counter,
+    rmc_requests: VecDeque<RMCRequest>,
+    is_dispatching_rmc: bool,
+    send_queue: SendQueue,
+    indexes: Weak<ClientIndexes>,
+}
+
+/// A handle to a connected client.
+/// Clones share the same connection, so the packet handling path,
+/// the ping task, and RMC handlers can all hold one at the same time.
+/// Connection state is only locked for the duration of each method call.
+#[derive(Clone)]
+pub struct ClientConnection {
+    state: Arc<Mutex<ConnectionState>>,
+    packet_lock: Arc<AsyncMutex<()>>,
+    send_queue_notify: Arc<Notify>,
 }
 
 impl ClientConnection {
     pub fn new(address: SocketAddr, context: ClientContext, kick_timer: u32) -> Self {
         Self {
-            address,
-            session_id: 0,
-            pid: 0,
-            is_connected: true,
-            kick_timer,
-            context,
+            state: Arc::new(Mutex::new(ConnectionState {
+                address,
+                connection_id: 0,
+                session_id: 0,
+                pid: 0,
+                authentication: AuthenticationState::Unauthenticated,
+                is_connected: true,
+                kick_timer,
+                fragment_size: None,
+                context,
+                call_id_out: Counter::default(),
+                rmc_requests: VecDeque::new(),
+                is_dispatching_rmc: false,
+                send_queue: SendQueue::default(),
+                indexes: Weak::new(),
+            })),
+            packet_lock: Arc::new(AsyncMutex::new(())),
+            send_queue_notify: Arc::new(Notify::new()),
         }
     }
 
+    fn state(&self) -> MutexGuard<'_, ConnectionState> {
+        // None of the critical sections can leave the state half updated,
+        // so a poisoned lock is still safe to use.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Serializes incoming packet handling for this client.
+    /// RMC handlers run after this is released, so they never hold up pings or acks.
+    pub async fn lock_packet_handling(&self) -> OwnedMutexGuard<()> {
+        Arc::clone(&self.packet_lock).lock_owned().await
+    }
+
     pub fn flags_version(&self) -> u32 {
-        self.context.flags_version
+        self.state().context.flags_version
+    }
+
+    pub fn nex_version(&self) -> u32 {
+        self.state().context.nex_version
+    }
+
+    pub fn encode_packet(&mut self, packet: &mut PacketV1) -> ClientConnectionResult<Vec<u8>> {
+        let mut state = self.state();
+        state.context.encrypt_packet(packet);
+        Ok(packet.to_bytes(&state.context.signature_context)?)
+    }
+
+    /// Encrypts the whole payload, then splits it into sequenced fragments of at most `fragment_size` bytes.
+    /// Everything happens under a single lock, so fragments of concurrent sends can't interleave
+    /// in the cipher stream or in sequence order.
+    pub fn encode_fragmented_packet(
+        &mut self,
+        packet: &mut PacketV1,
+        fragment_size: usize,
+    ) -> ClientConnectionResult<Vec<Vec<u8>>> {
+        let fragments = plan_fragments(packet, fragment_size)?;
+        let encoded_packets = Self::encode_fragments(&mut self.state(), packet, &fragments)?;
+        Ok(encoded_packets.into_iter().map(|(_, data)| data).collect())
+    }
+
+    /// Fragments must come from [plan_fragments], which has already checked they fit in packets.
+    /// That way the cipher stream and sequence ids only advance for fragments that can be sent.
+    fn encode_fragments(
+        state: &mut ConnectionState,
+        packet: &mut PacketV1,
+        fragments: &FragmentPlan,
+    ) -> ClientConnectionResult<Vec<(u16, Vec<u8>)>> {
+        state.context.encrypt_packet(packet);
+
+        // Fragments are split off the encrypted payload without copying it
+        let mut payload = packet.take_payload();
+        let mut encoded_packets = Vec::with_capacity(fragments.fragments.len());
+
+        for &(fragment_id, fragment_size) in &fragments.fragments {
+            let fragment = payload.split_to(fragment_size);
+            let sequence_id = state.context.increment_sequence_id_out();
+            packet.set_sequence_id(sequence_id);
+            packet.set_fragment_id(fragment_id);
+            packet.set_payload(fragment);
+            encoded_packets.push((
+                sequence_id,
+                packet.to_bytes(&state.context.signature_context)?,
+            ));
+        }
+
+        Ok(encoded_packets)
+    }
+
+    /// Encodes a packet's fragments and adds them to the send queue.
+    /// Fails without encrypting anything or using any sequence ids if a fragment doesn't fit in a packet,
+    /// or the queue can't hold the payload.
+    pub fn queue_packet(
+        &mut self,
+        packet: &mut PacketV1,
+        fragment_size: usize,
+        max_queued_bytes: usize,
+    ) -> ClientConnectionResult<()> {
+        let fragments = plan_fragments(packet, fragment_size)?;
+
+        {
+            let mut state = self.state();
+            let queued_bytes = state.send_queue.queued_bytes();
+
+            // The queue counts encoded datagrams, so headers and options of every fragment count too
+            if !state
+                .send_queue
+                .can_queue(fragments.encoded_size, max_queued_bytes)
+            {
+                return Err(Error::SendQueueFull {
+                    queued_bytes,
+                    size: fragments.encoded_size,
+                    max_queued_bytes,
+                });
+            }
+
+            let needs_ack = packet.get_flags().needs_ack();
+            for (sequence_id, data) in Self::encode_fragments(&mut state, packet, &fragments)? {
+                state.send_queue.push(sequence_id, data, needs_ack);
+            }
+        }
+
+        self.send_queue_notify.notify_one();
+        Ok(())
+    }
+
+    /// Claims the send queue.
+    /// Returns false if another task is already sending, or there's nothing to send.
+    pub fn start_send_queue(&mut self) -> bool {
+        self.state().send_queue.start_sending()
+    }
+
+    /// Takes the next action for a claimed send queue.
+    /// [SendQueuePoll::Idle] releases the claim.
+    pub fn poll_send_queue(&mut self) -> SendQueuePoll {
+        let mut state = self.state();
+
+        if !state.is_connected {
+            state.send_queue.clear();
+        }
+
+        state.send_queue.poll(Instant::now())
+    }
+
+    /// Waits until packets are queued or acknowledged.
+    pub async fn send_queue_changed(&self) {
+        self.send_queue_notify.notified().await;
     }
 
-    pub fn encode_packet(&mut self, packet: &mut PacketV1) -> Vec<u8> {
-        self.context.encrypt_packet(packet);
-        packet.to_bytes(&self.context.signature_context)
+    pub fn clear_send_queue(&mut self) {
+        self.state().send_queue.clear();
+        self.send_queue_notify.notify_one();
     }
 
-    pub fn validate_packet(&mut self, packet: &PacketV1) -> PacketResult<()> {
-        packet.validate(&self.context.signature_context)
+    pub fn get_queued_bytes(&self) -> usize {
+        self.state().send_queue.queued_bytes()
+    }
+
+    pub fn acknowledge_sequence_id(&mut self, sequence_id: u16) {
+        if self
+            .state()
+            .send_queue
+            .acknowledge(sequence_id, Instant::now())
+        {
+            self.send_queue_notify.notify_one();
+        }
+    }
+
+    /// Acknowledges every sequence id up to and including `sequence_id`, along with any additional ones.
+    pub fn acknowledge_sequence_ids(&mut self, sequence_id: u16, additional_ids: &[u16]) {
+        {
+            let mut state = self.state();
+            let now = Instant::now();
+            state.send_queue.acknowledge_up_to(sequence_id, now);
+
+            for additional_id in additional_ids {
+                state.send_queue.acknowledge(*additional_id, now);
+            }
+        }
+
+        self.send_queue_notify.notify_one();
+    }
+
+    /// Assigns the next unreliable sequence id and encodes the packet with its own cipher stream.
+    pub fn encode_unreliable_packet(
+        &mut self,
+        packet: &mut PacketV1,
+    ) -> ClientConnectionResult<Vec<u8>> {
+        let mut state = self.state();
+        let sequence_id = state.context.increment_unreliable_sequence_id_out();
+        packet.set_sequence_id(sequence_id);
+        state.context.encrypt_unreliable_packet(packet)?;
+        Ok(packet.to_bytes(&state.context.signature_context)?)
+    }
+
+    pub fn validate_packet(&self, packet: &PacketV1) -> PacketResult<()> {
+        packet.validate(&self.state().context.signature_context)
+    }
+
+    pub fn validate_packet_signature(&self, packet: &PacketV1) -> PacketResult<()> {
+        packet.validate_signature(&self.state().context.signature_context)
     }
 
     pub fn new_data_packet(&self, payload: Vec<u8>) -> PacketV1 {
+        let state = self.state();
         PacketV1::new_data_packet(
-            self.session_id,
-            self.context
+            state.session_id,
+            state
+                .context
+                .signature_context
+                .client_connection_signature()
+                .to_vec(),
+            payload,
+            state.context.flags_version,
+        )
+    }
+
+    pub fn new_unreliable_data_packet(&self, payload: Vec<u8>) -> PacketV1 {
+        let state = self.state();
+        PacketV1::new_unreliable_data_packet(
+            state.session_id,
+            state
+                .context
                 .signature_context
                 .client_connection_signature()
                 .to_vec(),
             payload,
-            self.context.flags_version,
+            state.context.flags_version,
         )
     }
 
     pub fn new_rmc_success(
         &self,
         protocol_id: u8,
+        custom_id: u16,
         method_id: impl Into<u32>,
         call_id: u32,
         data: impl Into<Vec<u8>>,
-    ) -> PacketV1 {
-        let rmc_response = RMCResponse::new_success(protocol_id, method_id, call_id, data.into());
-        self.new_data_packet(rmc_response.into())
+    ) -> ClientConnectionResult<PacketV1> {
+        let rmc_response = RMCResponse::new_success(protocol_id, method_id, call_id, data.into())
+            .with_custom_id(custom_id);
+        Ok(self.new_data_packet(rmc_response.try_into()?))
     }
 
     pub fn new_rmc_error(
         &self,
         protocol_id: u8,
+        custom_id: u16,
         method_id: impl Into<u32>,
         call_id: u32,
         error_code: u32,
-    ) -> PacketV1 {
-        let rmc_response = RMCResponse::new_error(protocol_id, method_id, call_id, error_code);
-        self.new_data_packet(rmc_response.into())
+    ) -> ClientConnectionResult<PacketV1> {
+        let rmc_response = RMCResponse::new_error(protocol_id, method_id, call_id, error_code)
+            .with_custom_id(custom_id);
+        Ok(self.new_data_packet(rmc_response.try_into()?))
+    }
+
+    /// Creates a server to client RMC request with the next call id for this client.
+    /// Requests to an extended protocol are sent with the protocol's custom id.
+    pub fn new_rmc_request<Method: NexProtocol + Into<u32>>(
+        &mut self,
+        method: Method,
+        parameters: Vec<u8>,
+    ) -> ClientConnectionResult<PacketV1> {
+        let call_id = self.state().call_id_out.increment();
+        let rmc_request = RMCRequest::new(Method::PROTOCOL_ID, method, call_id, parameters)
+            .with_custom_id(Method::CUSTOM_ID);
+        Ok(self.new_data_packet(rmc_request.try_into()?))
     }
 
     pub fn get_session_id(&self) -> u8 {
-        self.session_id
+        self.state().session_id
     }
 
     pub fn set_session_key(&mut self, key: Vec<u8>) {
-        self.context.signature_context.set_session_key(key);
+        self.state().context.set_session_key(key);
     }
 
     pub fn set_client_connection_signature(&mut self, client_connection_signature: Vec<u8>) {
-        self.context
+        self.state()
+            .context
             .signature_context
             .set_client_connection_signature(client_connection_signature);
     }
 
-    pub fn get_client_connection_signature(&mut self) -> &[u8] {
-        self.context.signature_context.client_connection_signature()
+    pub fn get_client_connection_signature(&self) -> Vec<u8> {
+        self.state()
+            .context
+            .signature_context
+            .client_connection_signature()
+            .to_vec()
     }
 
     pub fn set_server_connection_signature(&mut self, server_connection_signature: Vec<u8>) {
-        self.context
+        self.state()
+            .context
             .signature_context
             .set_server_connection_signature(server_connection_signature);
     }
 
-    pub fn get_server_connection_signature(&self) -> &[u8] {
-        self.context.signature_context.server_connection_signature()
+    pub fn get_server_connection_signature(&self) -> Vec<u8> {
+        self.state()
+            .context
+            .signature_context
+            .server_connection_signature()
+            .to_vec()
     }
 
     pub fn is_connected(&self) -> bool {
-        self.is_connected
+        self.state().is_connected
     }
 
     pub fn set_is_connected(&mut self, is_connected: bool) {
-        self.is_connected = is_connected;
+        self.state().is_connected = is_connected;
     }
 
     pub fn get_address(&self) -> SocketAddr {
-        self.address
+        self.state().address
+    }
+
+    pub(super) fn set_address(&self, address: SocketAddr) {
+        self.state().address = address;
     }
 
     pub fn get_pid(&self) -> u32 {
-        self.pid
+        self.state().pid
     }
 
     pub fn set_pid(&mut self, pid: u32) {
-        self.pid = pid;
+        let (old_pid, indexes) = {
+            let mut state = self.state();
+            let old_pid = state.pid;
+            state.pid = pid;
+            (old_pid, state.indexes.upgrade())
+        };
+
+        // The state lock is released first, since the registry locks its indexes
+        // before checking on the client
+        if let Some(indexes) = indexes {
+            indexes.update_pid(self, old_pid, pid);
+        }
+    }
+
+    pub fn authentication_state(&self) -> AuthenticationState {
+        self.state().authentication
+    }
+
+    /// Marks the client as `pid`, once it has proven it with a valid Kerberos ticket.
+    pub fn authenticate(&mut self, pid: u32) {
+        self.state().authentication = AuthenticationState::Authenticated { pid };
+        self.set_pid(pid);
+    }
+
+    /// Also resets the pid, so it isn't trusted or found by pid after the client logs out.
+    pub fn clear_authentication(&mut self) {
+        self.state().authentication = AuthenticationState::Unauthenticated;
+        self.set_pid(0);
+    }
+
+    /// Returns the authenticated pid, or RendezVous::NotAuthenticated if the client hasn't authenticated.
+    pub fn require_authentication(&self) -> Result<u32, ResultCode> {
+        match self.authentication_state() {
+            AuthenticationState::Authenticated { pid } => Ok(pid),
+            AuthenticationState::Unauthenticated => Err(RendezVous::NotAuthenticated.into()),
+        }
+    }
+
+    /// Also rejects authenticated clients other than `owner_pid` with RendezVous::PermissionDenied.
+    pub fn require_owner(&self, owner_pid: u32) -> Result<(), ResultCode> {
+        if self.require_authentication()? != owner_pid {
+            return Err(RendezVous::PermissionDenied.into());
+        }
+        Ok(())
+    }
+
+    pub fn get_connection_id(&self) -> u32 {
+        self.state().connection_id
+    }
+
+    /// Whether both handles refer to the same connection.
+    pub fn is_same_connection(&self, other: &ClientConnection) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    pub(super) fn attach_indexes(&self, connection_id: u32, indexes: &Arc<ClientIndexes>) {
+        let mut state = self.state();
+        state.connection_id = connection_id;
+        state.indexes = Arc::downgrade(indexes);
+    }
+
+    /// Stops the client from updating the registry indexes, returning its current pid.
+    pub(super) fn detach_indexes(&self) -> u32 {
+        let mut state = self.state();
+        state.indexes = Weak::new();
+        state.pid
+    }
+
+    pub(super) fn is_indexed(&self) -> bool {
+        self.state().indexes.strong_count() > 0
     }
 
-    pub fn get_mut_context(&mut self) -> &mut ClientContext {
-        &mut self.context
+    pub fn with_mut_context<T>(&mut self, callback: impl FnOnce(&mut ClientContext) -> T) -> T {
+        callback(&mut self.state().context)
     }
 
     pub fn get_sequence_id_in(&self) -> u16 {
-        self.context.get_sequence_id_in()
+        self.state().context.get_sequence_id_in()
     }
 
     pub fn increment_sequence_id_in(&mut self) -> u16 {
-        self.context.increment_sequence_id_in()
+        self.state().context.increment_sequence_id_in()
     }
 
     pub fn increment_sequence_id_out(&mut self) -> u16 {
-        self.context.increment_sequence_id_out()
+        self.state().context.increment_sequence_id_out()
     }
 
     pub fn update_rc4_key(&mut self, rc4_key: &[u8]) {
-        self.context.cipher = Rc4::new(rc4_key);
-        self.context.decipher = Rc4::new(rc4_key);
+        let mut state = self.state();
+        state.context.cipher = Rc4::new(rc4_key);
+        state.context.decipher = Rc4::new(rc4_key);
+    }
+
+    /// The fragment size set for this client, or its title's fragment size.
+    pub fn get_fragment_size(&self) -> u16 {
+        let state = self.state();
+        state.fragment_size.unwrap_or(state.context.fragment_size)
+    }
+
+    pub fn set_fragment_size(&mut self, fragment_size: u16) {
+        self.state().fragment_size = Some(fragment_size);
     }
 
     pub fn get_kick_timer(&self) -> u32 {
-        self.kick_timer
+        self.state().kick_timer
     }
 
     pub fn set_kick_timer(&mut self, seconds: u32) {
-        self.kick_timer = seconds;
+        self.state().kick_timer = seconds;
     }
 
     pub fn decrement_kick_timer(&mut self, seconds: u32) {
-        self.kick_timer = self.kick_timer.saturating_sub(seconds);
+        let mut state = self.state();
+        state.kick_timer = state.kick_timer.saturating_sub(seconds);
     }
 
     pub fn can_decode_rmc_request(&self, packet: &PacketV1) -> bool {
-        self.context.can_decrypt_packet(packet).is_ok()
+        !packet.is_unreliable_data() && self.state().context.can_decrypt_packet(packet).is_ok()
+    }
+
+    /// Decrypts an unreliable data packet's payload in place.
+    pub fn decrypt_unreliable_data(&mut self, packet: &mut PacketV1) -> ClientConnectionResult<()> {
+        if !packet.is_unreliable_data() {
+            return Err("Expected an unreliable data packet".into());
+        }
+
+        self.state().context.decrypt_packet(packet)
+    }
+
+    /// Decrypts the packet's payload in place, then reads an RMC request from it.
+    pub fn decode_rmc_request(
+        &mut self,
+        packet: &mut PacketV1,
+    ) -> ClientConnectionResult<RMCRequest> {
+        self.state().context.decrypt_packet(packet)?;
+        packet
+            .get_payload()
+            .read_le(0)
+            .map_err(|_| Error::InvalidPacketRead {
+                packet_type: packet.get_packet_type(),
+                sequence_id: packet.get_sequence_id(),
+                message: "Cannot read rmc request from payload".into(),
+            })
+    }
+
+    pub fn queue_rmc_request(&mut self, rmc_request: RMCRequest) {
+        self.state().rmc_requests.push_back(rmc_request);
+    }
+
+    /// Claims the RMC request queue.
+    /// Returns false if another task is already dispatching this client's requests.
+    pub fn start_rmc_dispatch(&mut self) -> bool {
+        let mut state = self.state();
+
+        if state.is_dispatching_rmc || state.rmc_requests.is_empty() {
+            return false;
+        }
+
+        state.is_dispatching_rmc = true;
+        true
+    }
+
+    /// Takes the next queued RMC request, releasing the queue once it's empty.
+    pub fn next_rmc_request(&mut self) -> Option<RMCRequest> {
+        let mut state = self.state();
+        let rmc_request = state.rmc_requests.pop_front();
+
+        if rmc_request.is_none() {
+            state.is_dispatching_rmc = false;
+        }
+
+        rmc_request
+    }
+}
+
+/// The fragments a packet's payload is sent as, and their total encoded size.
+struct FragmentPlan {
+    /// Each fragment's id and payload size.
+    fragments: Vec<(u8, usize)>,
+    encoded_size: usize,
+}
+
+/// Splits a packet's payload into fragments, checking each one fits in a packet.
+/// Nothing is encrypted or sequenced yet, so failing here leaves the connection untouched.
+fn plan_fragments(packet: &mut PacketV1, fragment_size: usize) -> PacketResult<FragmentPlan> {
+    let fragments: Vec<(u8, usize)> = split_fragments(packet.get_payload(), fragment_size)?
+        .into_iter()
+        .map(|(fragment_id, fragment)| (fragment_id, fragment.len()))
+        .collect();
+
+    let mut encoded_size = 0;
+    for &(fragment_id, fragment_size) in &fragments {
+        packet.set_fragment_id(fragment_id);
+        encoded_size += packet.encoded_size(fragment_size)?;
+    }
+
+    Ok(FragmentPlan {
+        fragments,
+        encoded_size,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::FragmentAssembler;
+
+    fn new_client() -> ClientConnection {
+        let addr = "127.0.0.1:12345".parse().unwrap();
+        ClientConnection::new(addr, ClientContext::default(), 0)
+    }
+
+    #[test]
+    fn should_share_state_between_handles() {
+        let mut client = new_client();
+        let handle = client.clone();
+        client.set_pid(1234);
+        assert_eq!(handle.get_pid(), 1234);
+    }
+
+    #[test]
+    fn should_require_authentication() {
+        let mut client = new_client();
+        assert_eq!(
+            client.require_authentication(),
+            Err(RendezVous::NotAuthenticated.into())
+        );
+        assert_eq!(
+            client.require_owner(1234),
+            Err(RendezVous::NotAuthenticated.into())
+        );
+
+        client.authenticate(1234);
+        assert_eq!(client.get_pid(), 1234);
+        assert_eq!(client.require_authentication(), Ok(1234));
+        assert_eq!(client.require_owner(1234), Ok(()));
+        assert_eq!(
+            client.require_owner(5678),
+            Err(RendezVous::PermissionDenied.into())
+        );
+
+        client.clear_authentication();
+        assert_eq!(
+            client.authentication_state(),
+            AuthenticationState::Unauthenticated
+        );
+        assert_eq!(client.get_pid(), 0);
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum ExtendedMethod {
+        Notify = 3,
+    }
+
+    impl From<ExtendedMethod> for u32 {
+        fn from(method: ExtendedMethod) -> Self {
+            method as u32
+        }
+    }
+
+    impl NexProtocol for ExtendedMethod {
+        const PROTOCOL_ID: u8 = crate::route::EXTENDED_PROTOCOL_ID;
+        const CUSTOM_ID: u16 = 0x1234;
+    }
+
+    #[test]
+    fn should_send_rmc_requests_with_the_custom_id() {
+        let mut client = new_client();
+        let packet = client
+            .new_rmc_request(ExtendedMethod::Notify, vec![0xaa])
+            .expect("Should have succeeded!");
+
+        let request: RMCRequest = packet
+            .get_payload()
+            .read_le(0)
+            .expect("Should have succeeded!");
+        assert!(request.is_method(ExtendedMethod::Notify));
+        assert_eq!(request.custom_id, 0x1234);
+        assert_eq!(request.call_id, 1);
+        assert_eq!(request.parameters, vec![0xaa]);
     }
 
-    pub fn decode_rmc_request(&mut self, packet: &PacketV1) -> ClientConnectionResult<RMCRequest> {
-        let payload = self.context.decrypt_packet(packet)?;
-        payload.read_le(0).map_err(|_| Error::InvalidPacketRead {
-            packet_type: packet.get_packet_type(),
-            sequence_id: packet.get_sequence_id(),
-            message: "Cannot read rmc request from payload".into(),
-        })
+    #[test]
+    fn should_only_allow_one_rmc_dispatcher() {
+        let mut client = new_client();
+        let mut handle = client.clone();
+        client.queue_rmc_request(RMCRequest::default());
+
+        assert!(client.start_rmc_dispatch());
+        assert!(!handle.start_rmc_dispatch());
+        assert!(client.next_rmc_request().is_some());
+        assert!(client.next_rmc_request().is_none());
+
+        handle.queue_rmc_request(RMCRequest::default());
+        assert!(handle.start_rmc_dispatch());
+    }
+
+    #[test]
+    fn should_encode_reassemblable_fragments() {
+        let mut client = new_client();
+        let payload: Vec<u8> = (0..1000).map(|value| value as u8).collect();
+        let mut packet = client.new_data_packet(payload.clone());
+
+        let encoded_packets = client
+            .encode_fragmented_packet(&mut packet, 100)
+            .expect("Should have succeeded!");
+        assert_eq!(encoded_packets.len(), 10);
+
+        let mut assembler = FragmentAssembler::new();
+        let mut reassembled = None;
+
+        for (index, encoded_packet) in encoded_packets.into_iter().enumerate() {
+            let packet = PacketV1::read_packet(encoded_packet.as_slice(), client.flags_version())
+                .expect("Should have succeeded!");
+            assert_eq!(packet.get_sequence_id(), index as u16 + 1);
+            reassembled = assembler
+                .push(packet.get_fragment_id(), packet.get_payload())
+                .expect("Should have succeeded!");
+        }
+
+        let encrypted = reassembled.expect("Should have a payload");
+        let decrypted = Rc4::new(b"CD&ML")
+            .decrypt(&encrypted)
+            .expect("Should have succeeded!");
+        assert_eq!(decrypted, payload);
+    }
+
+    #[test]
+    fn should_reject_packets_past_the_queue_limit() {
+        let mut client = new_client();
+        let mut packet = client.new_data_packet(vec![0; 100]);
+        client
+            .queue_packet(&mut packet, 1000, 150)
+            .expect("Should have succeeded!");
+        let queued_bytes = client.get_queued_bytes();
+
+        let mut packet = client.new_data_packet(vec![0; 100]);
+        assert_eq!(
+            client.queue_packet(&mut packet, 1000, 150),
+            Err(Error::SendQueueFull {
+                queued_bytes,
+                size: queued_bytes,
+                max_queued_bytes: 150,
+            })
+        );
+        assert_eq!(client.state().context.sequence_id_out.value(), 1);
+    }
+
+    #[test]
+    fn should_count_fragment_overhead_against_the_queue_limit() {
+        let mut client = new_client();
+        let mut packet = client.new_data_packet(vec![0; 100]);
+        let encoded_size = 2 * packet.encoded_size(50).expect("Should have succeeded!");
+
+        assert_eq!(
+            client.queue_packet(&mut packet, 50, encoded_size - 1),
+            Err(Error::SendQueueFull {
+                queued_bytes: 0,
+                size: encoded_size,
+                max_queued_bytes: encoded_size - 1,
+            })
+        );
+
+        client
+            .queue_packet(&mut packet, 50, encoded_size)
+            .expect("Should have succeeded!");
+        assert_eq!(client.get_queued_bytes(), encoded_size);
+    }
+
+    #[test]
+    fn should_reject_fragments_that_do_not_fit_in_a_packet() {
+        let mut client = new_client();
+        let mut packet = client.new_data_packet(vec![0; 0x10001]);
+
+        assert_eq!(
+            client.encode_fragmented_packet(&mut packet, 0x10001),
+            Err(Error::PacketError {
+                error: crate::packet::Error::PayloadTooLarge { size: 0x10001 }
+            })
+        );
+        assert_eq!(
+            client.queue_packet(&mut packet, 0x10001, usize::MAX),
+            Err(Error::PacketError {
+                error: crate::packet::Error::PayloadTooLarge { size: 0x10001 }
+            })
+        );
+        assert_eq!(client.state().context.sequence_id_out.value(), 0);
+        assert_eq!(client.get_queued_bytes(), 0);
+
+        // The cipher stream didn't advance, so a packet that fits still decrypts from the start
+        let payload = vec![1, 2, 3];
+        let mut packet = client.new_data_packet(payload.clone());
+        let encoded_packets = client
+            .encode_fragmented_packet(&mut packet, 100)
+            .expect("Should have succeeded!");
+        let packet = PacketV1::read_packet(encoded_packets[0].as_slice(), client.flags_version())
+            .expect("Should have succeeded!");
+        assert_eq!(packet.get_sequence_id(), 1);
+        let decrypted = Rc4::new(b"CD&ML")
+            .decrypt(packet.get_payload())
+            .expect("Should have succeeded!");
+        assert_eq!(decrypted, payload);
     }
 }
@@ -1,22 +1,28 @@
 use super::ClientConnectionResult;
 use crate::{
-    counter::Counter,
-    crypto::{rc4::Rc4, CryptResult},
+    counter::SequenceIdCounter,
+    crypto::{md5, rc4::Rc4, CryptResult},
     packet::{Packet, PacketType, PacketV1, SignatureContext},
 };
-use getset::{CopyGetters, Getters};
+use getset::{CopyGetters, Getters, Setters};
 
-#[derive(Clone, CopyGetters, Getters)]
+#[derive(Clone, CopyGetters, Getters, Setters)]
 #[getset(skip)]
 pub struct ClientContext {
     #[getset(get_copy = "pub")]
     pub(super) flags_version: u32,
+    #[getset(get_copy = "pub", set = "pub")]
+    pub(super) nex_version: u32,
+    #[getset(get_copy = "pub", set = "pub")]
+    pub(super) fragment_size: u16,
     #[getset(get_copy = "pub")]
     pub(super) signature_base: u32,
     pub(super) cipher: Rc4,
     pub(super) decipher: Rc4,
-    pub(super) sequence_id_in: Counter,
-    pub(super) sequence_id_out: Counter,
+    pub(super) sequence_id_in: SequenceIdCounter,
+    pub(super) sequence_id_out: SequenceIdCounter,
+    pub(super) unreliable_sequence_id_out: SequenceIdCounter,
+    pub(super) unreliable_base_key: Vec<u8>,
     pub(super) signature_context: SignatureContext,
 }
 
@@ -38,24 +44,57 @@ impl ClientContext {
     }
 
     pub fn get_sequence_id_in(&self) -> u16 {
-        self.sequence_id_in
-            .value()
-            .try_into()
-            .expect("Sequence id out does not fit into u16")
+        self.sequence_id_in.value()
     }
 
     pub fn increment_sequence_id_in(&mut self) -> u16 {
-        self.sequence_id_in
-            .increment()
-            .try_into()
-            .expect("Sequence id out does not fit into u16")
+        self.sequence_id_in.increment()
     }
 
     pub fn increment_sequence_id_out(&mut self) -> u16 {
-        self.sequence_id_out
-            .increment()
-            .try_into()
-            .expect("Sequence id out does not fit into u16")
+        self.sequence_id_out.increment()
+    }
+
+    pub fn increment_unreliable_sequence_id_out(&mut self) -> u16 {
+        self.unreliable_sequence_id_out.increment()
+    }
+
+    /// Sets the session key, along with the base key for unreliable data packets derived from it.
+    pub fn set_session_key(&mut self, session_key: Vec<u8>) {
+        let (first_half, second_half) = session_key.split_at(session_key.len() / 2);
+        let first_part = md5::hash(
+            &[
+                first_half,
+                &[0x18, 0xd8, 0x23, 0x34, 0x37, 0xe4, 0xe3, 0xfe],
+            ]
+            .concat(),
+        );
+        let second_part = md5::hash(
+            &[
+                second_half,
+                &[0x23, 0x3e, 0x60, 0x01, 0x23, 0xcd, 0xab, 0x80],
+            ]
+            .concat(),
+        );
+
+        self.unreliable_base_key = [first_part, second_part].concat();
+        self.signature_context.set_session_key(session_key);
+    }
+
+    /// Unreliable data packets can arrive in any order, so each one is encrypted with its own
+    /// RC4 stream, keyed by the unreliable base key modified with its sequence and session ids.
+    fn unreliable_cipher(&self, packet: &PacketV1) -> ClientConnectionResult<Rc4> {
+        if self.unreliable_base_key.is_empty() {
+            return Err("Unreliable data packets need a session key".into());
+        }
+
+        let sequence_id = packet.get_sequence_id().to_le_bytes();
+        let mut key = self.unreliable_base_key.clone();
+        key[0] = key[0].wrapping_add(sequence_id[0]);
+        key[1] = key[1].wrapping_add(sequence_id[1]);
+        key[31] = key[31].wrapping_add(packet.get_session_id());
+
+        Ok(Rc4::new(&key))
     }
 
     pub(super) fn can_decrypt_packet(&self, packet: &PacketV1) -> ClientConnectionResult<()> {
@@ -67,18 +106,25 @@ impl ClientContext {
             return Err("Ack packets can not hold payloads".into());
         }
 
-        if packet.get_sequence_id() != self.get_sequence_id_in() {
+        if !packet.is_unreliable_data() && packet.get_sequence_id() != self.get_sequence_id_in() {
             return Err("Tried to decode a packet out of order".into());
         }
 
         Ok(())
     }
 
-    pub(super) fn decrypt_packet(&mut self, packet: &PacketV1) -> ClientConnectionResult<Vec<u8>> {
+    /// Decrypts a packet's payload in place.
+    pub(super) fn decrypt_packet(&mut self, packet: &mut PacketV1) -> ClientConnectionResult<()> {
         self.can_decrypt_packet(packet)?;
-        self.decipher
-            .decrypt(packet.get_payload())
-            .map_err(|error| error.into())
+
+        if packet.is_unreliable_data() {
+            self.unreliable_cipher(packet)?
+                .apply_in_place(packet.get_mut_payload());
+        } else {
+            self.decipher.apply_in_place(packet.get_mut_payload());
+        }
+
+        Ok(())
     }
 
     fn can_encrypt_packet(&self, packet: &PacketV1) -> Result<(), &'static str> {
@@ -97,11 +143,23 @@ impl ClientContext {
         Ok(())
     }
 
+    /// Encrypts a packet's payload in place.
     pub(super) fn encrypt_packet(&mut self, packet: &mut PacketV1) {
         if self.can_encrypt_packet(packet).is_ok() {
-            let payload = self.cipher.encrypt(packet.get_payload()).unwrap();
-            packet.set_payload(payload);
+            self.cipher.apply_in_place(packet.get_mut_payload());
+        }
+    }
+
+    pub(super) fn encrypt_unreliable_packet(
+        &mut self,
+        packet: &mut PacketV1,
+    ) -> ClientConnectionResult<()> {
+        if self.can_encrypt_packet(packet).is_ok() {
+            self.unreliable_cipher(packet)?
+                .apply_in_place(packet.get_mut_payload());
         }
+
+        Ok(())
     }
 }
 
@@ -111,10 +169,92 @@ impl Default for ClientContext {
             cipher: Rc4::new(b"CD&ML"),
             decipher: Rc4::new(b"CD&ML"),
             flags_version: 1,
+            nex_version: 0,
+            fragment_size: 1300,
             signature_base: 0,
-            sequence_id_in: Counter::default(),
-            sequence_id_out: Counter::default(),
+            sequence_id_in: SequenceIdCounter::default(),
+            sequence_id_out: SequenceIdCounter::default(),
+            unreliable_sequence_id_out: SequenceIdCounter::default(),
+            unreliable_base_key: vec![],
             signature_context: SignatureContext::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_data_packet(sequence_id: u16) -> PacketV1 {
+        let flags_version = 1;
+        let mut packet = PacketV1::new_data_packet(0, vec![], vec![0xaa], flags_version);
+        packet.set_sequence_id(sequence_id);
+        packet
+    }
+
+    #[test]
+    fn should_increment_sequence_ids_past_rollover() {
+        let mut context = ClientContext {
+            sequence_id_in: SequenceIdCounter::new(0xfffe),
+            sequence_id_out: SequenceIdCounter::new(0xfffe),
+            ..Default::default()
+        };
+
+        assert_eq!(context.increment_sequence_id_in(), 0xffff);
+        assert_eq!(context.increment_sequence_id_in(), 0);
+        assert_eq!(context.increment_sequence_id_out(), 0xffff);
+        assert_eq!(context.increment_sequence_id_out(), 0);
+    }
+
+    #[test]
+    fn should_decrypt_packets_across_rollover() {
+        let mut context = ClientContext {
+            sequence_id_in: SequenceIdCounter::new(0xffff),
+            ..Default::default()
+        };
+
+        assert_eq!(context.can_decrypt_packet(&new_data_packet(0xffff)), Ok(()));
+        context.increment_sequence_id_in();
+        assert_eq!(context.can_decrypt_packet(&new_data_packet(0)), Ok(()));
+        assert!(context
+            .can_decrypt_packet(&new_data_packet(0xffff))
+            .is_err());
+    }
+
+    fn new_unreliable_packet(context: &mut ClientContext, payload: Vec<u8>) -> PacketV1 {
+        let flags_version = 1;
+        let mut packet = PacketV1::new_unreliable_data_packet(1, vec![], payload, flags_version);
+        packet.set_sequence_id(context.increment_unreliable_sequence_id_out());
+        context
+            .encrypt_unreliable_packet(&mut packet)
+            .expect("Should have succeeded!");
+        packet
+    }
+
+    #[test]
+    fn should_decrypt_unreliable_packets_out_of_order() {
+        let session_key = vec![0x5a; 32];
+        let mut sender = ClientContext::default();
+        let mut receiver = ClientContext::default();
+        sender.set_session_key(session_key.clone());
+        receiver.set_session_key(session_key);
+
+        let mut first = new_unreliable_packet(&mut sender, vec![1, 2, 3]);
+        let mut second = new_unreliable_packet(&mut sender, vec![4, 5, 6]);
+        assert_ne!(first.get_payload(), [1, 2, 3]);
+
+        assert_eq!(receiver.decrypt_packet(&mut second), Ok(()));
+        assert_eq!(second.get_payload(), [4, 5, 6]);
+        assert_eq!(receiver.decrypt_packet(&mut first), Ok(()));
+        assert_eq!(first.get_payload(), [1, 2, 3]);
+        assert_eq!(receiver.get_sequence_id_in(), 0);
+    }
+
+    #[test]
+    fn should_require_a_session_key_for_unreliable_packets() {
+        let mut context = ClientContext::default();
+        let flags_version = 1;
+        let mut packet = PacketV1::new_unreliable_data_packet(0, vec![], vec![1], flags_version);
+        assert!(context.encrypt_unreliable_packet(&mut packet).is_err());
+    }
+}
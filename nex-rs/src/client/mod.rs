@@ -1,7 +1,13 @@
+mod authentication;
 mod connection;
 mod context;
+mod registry;
 mod result;
+mod send_queue;
 
+pub use authentication::*;
 pub use connection::*;
 pub use context::*;
+pub use registry::*;
 pub use result::*;
+pub use send_queue::*;
@@ -0,0 +1,429 @@
+use super::ClientConnection;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+const SHARD_COUNT: usize = 32;
+
+// None of the critical sections in this file can leave a map half updated,
+// so a poisoned lock is still safe to use.
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+type ClientIndex = RwLock<HashMap<u32, ClientConnection>>;
+type ClientShard = RwLock<HashMap<SocketAddr, ClientConnection>>;
+
+fn remove_if_same(index: &ClientIndex, key: u32, client: &ClientConnection) {
+    let mut index = write(index);
+    if index
+        .get(&key)
+        .is_some_and(|indexed| indexed.is_same_connection(client))
+    {
+        index.remove(&key);
+    }
+}
+
+/// Secondary indexes shared between the registry and its clients,
+/// so a client can keep its own entries up to date.
+#[derive(Default)]
+pub(super) struct ClientIndexes {
+    pids: ClientIndex,
+    connection_ids: ClientIndex,
+}
+
+impl ClientIndexes {
+    pub(super) fn update_pid(&self, client: &ClientConnection, old_pid: u32, new_pid: u32) {
+        let mut pids = write(&self.pids);
+
+        if pids
+            .get(&old_pid)
+            .is_some_and(|indexed| indexed.is_same_connection(client))
+        {
+            pids.remove(&old_pid);
+        }
+
+        // The client may have been removed while its pid was changing
+        if new_pid != 0 && client.is_indexed() {
+            pids.insert(new_pid, client.clone());
+        }
+    }
+
+    fn remove(&self, client: &ClientConnection) {
+        let pid = client.detach_indexes();
+        remove_if_same(&self.pids, pid, client);
+        remove_if_same(&self.connection_ids, client.get_connection_id(), client);
+    }
+}
+
+/// The set of connected clients, indexed by address, pid, and connection id.
+/// Clients are spread over several shards so that packets from different
+/// clients rarely contend for the same lock, and no lock is ever held across an await.
+pub struct ClientRegistry {
+    shards: Vec<ClientShard>,
+    indexes: Arc<ClientIndexes>,
+    connection_id_counter: AtomicU32,
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARD_COUNT).map(|_| RwLock::default()).collect(),
+            indexes: Arc::default(),
+            connection_id_counter: AtomicU32::new(10),
+        }
+    }
+
+    // Clients are sharded by ip rather than the full address,
+    // so every client behind the same ip can be found in one shard
+    fn shard_index(&self, ip: IpAddr) -> usize {
+        let mut hasher = DefaultHasher::new();
+        ip.hash(&mut hasher);
+        (hasher.finish() % SHARD_COUNT as u64) as usize
+    }
+
+    fn shard(&self, address: &SocketAddr) -> &ClientShard {
+        &self.shards[self.shard_index(address.ip())]
+    }
+
+    /// Adds a client and assigns it a connection id.
+    /// Any client that was already at the same address is replaced and returned.
+    pub fn insert(&self, client: ClientConnection) -> Option<ClientConnection> {
+        let address = client.get_address();
+        let connection_id = self.connection_id_counter.fetch_add(1, Ordering::Relaxed) + 1;
+
+        client.attach_indexes(connection_id, &self.indexes);
+        write(&self.indexes.connection_ids).insert(connection_id, client.clone());
+        self.indexes.update_pid(&client, 0, client.get_pid());
+
+        let replaced = write(self.shard(&address)).insert(address, client);
+
+        if let Some(replaced) = &replaced {
+            self.indexes.remove(replaced);
+        }
+
+        replaced
+    }
+
+    pub fn get(&self, address: &SocketAddr) -> Option<ClientConnection> {
+        read(self.shard(address)).get(address).cloned()
+    }
+
+    pub fn get_by_pid(&self, pid: u32) -> Option<ClientConnection> {
+        read(&self.indexes.pids).get(&pid).cloned()
+    }
+
+    pub fn get_by_connection_id(&self, connection_id: u32) -> Option<ClientConnection> {
+        read(&self.indexes.connection_ids)
+            .get(&connection_id)
+            .cloned()
+    }
+
+    pub fn remove(&self, address: &SocketAddr) -> Option<ClientConnection> {
+        let client = write(self.shard(address)).remove(address)?;
+        self.indexes.remove(&client);
+        Some(client)
+    }
+
+    /// Removes the client only if it's still the one registered at its address,
+    /// so a client that reconnected in the meantime is left alone.
+    pub fn remove_client(&self, client: &ClientConnection) -> bool {
+        let address = client.get_address();
+        let mut shard = write(self.shard(&address));
+
+        if !shard
+            .get(&address)
+            .is_some_and(|registered| registered.is_same_connection(client))
+        {
+            return false;
+        }
+
+        shard.remove(&address);
+        drop(shard);
+
+        self.indexes.remove(client);
+        true
+    }
+
+    /// Moves a client to a new address.
+    /// Returns false if the client is no longer registered or the new address is taken.
+    pub fn migrate(&self, client: &ClientConnection, new_address: SocketAddr) -> bool {
+        let old_address = client.get_address();
+        let old_index = self.shard_index(old_address.ip());
+        let new_index = self.shard_index(new_address.ip());
+
+        // Shards are always locked in index order, so concurrent migrations can't deadlock
+        let (mut old_shard, mut new_shard) = if old_index == new_index {
+            (write(&self.shards[old_index]), None)
+        } else if old_index < new_index {
+            let old_shard = write(&self.shards[old_index]);
+            (old_shard, Some(write(&self.shards[new_index])))
+        } else {
+            let new_shard = write(&self.shards[new_index]);
+            (write(&self.shards[old_index]), Some(new_shard))
+        };
+
+        let is_registered = old_shard
+            .get(&old_address)
+            .is_some_and(|registered| registered.is_same_connection(client));
+        let is_address_taken = match &new_shard {
+            Some(new_shard) => new_shard.contains_key(&new_address),
+            None => old_shard.contains_key(&new_address),
+        };
+
+        if !is_registered || is_address_taken {
+            return false;
+        }
+
+        old_shard.remove(&old_address);
+        client.set_address(new_address);
+
+        match &mut new_shard {
+            Some(new_shard) => new_shard.insert(new_address, client.clone()),
+            None => old_shard.insert(new_address, client.clone()),
+        };
+
+        true
+    }
+
+    /// Returns a handle to every client behind an ip.
+    pub fn clients_with_ip(&self, ip: IpAddr) -> Vec<ClientConnection> {
+        read(&self.shards[self.shard_index(ip)])
+            .iter()
+            .filter(|(address, _)| address.ip() == ip)
+            .map(|(_, client)| client.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| read(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| read(shard).is_empty())
+    }
+
+    /// Returns a handle to every client.
+    /// Shards are locked one at a time and only while their handles are copied,
+    /// so this never stalls packet handling for more than a moment.
+    pub fn clients(&self) -> Vec<ClientConnection> {
+        self.shards
+            .iter()
+            .flat_map(|shard| read(shard).values().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        client::ClientContext,
+        route::NexProtocol,
+        server::{Server, ServerSettings, TestServer},
+    };
+
+    fn new_client(port: u16) -> ClientConnection {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        ClientConnection::new(addr, ClientContext::default(), 0)
+    }
+
+    #[test]
+    fn should_index_by_connection_id() {
+        let registry = ClientRegistry::new();
+        let client = new_client(1);
+        registry.insert(client.clone());
+
+        let connection_id = client.get_connection_id();
+        let found = registry
+            .get_by_connection_id(connection_id)
+            .expect("Client should be indexed");
+        assert!(found.is_same_connection(&client));
+    }
+
+    #[test]
+    fn should_index_by_pid() {
+        let registry = ClientRegistry::new();
+        let mut client = new_client(1);
+        registry.insert(client.clone());
+
+        client.set_pid(1234);
+        assert!(registry.get_by_pid(1234).is_some());
+
+        client.set_pid(5678);
+        assert!(registry.get_by_pid(1234).is_none());
+        assert!(registry.get_by_pid(5678).is_some());
+    }
+
+    #[test]
+    fn should_remove_indexes() {
+        let registry = ClientRegistry::new();
+        let mut client = new_client(1);
+        registry.insert(client.clone());
+        client.set_pid(1234);
+
+        assert!(registry.remove_client(&client));
+        assert!(registry.get_by_pid(1234).is_none());
+        assert!(registry
+            .get_by_connection_id(client.get_connection_id())
+            .is_none());
+        assert!(registry.is_empty());
+
+        // Removed clients shouldn't be indexed again
+        client.set_pid(5678);
+        assert!(registry.get_by_pid(5678).is_none());
+    }
+
+    #[test]
+    fn should_migrate_client() {
+        let registry = ClientRegistry::new();
+        let client = new_client(1);
+        let new_address = SocketAddr::from(([127, 0, 0, 1], 2));
+        registry.insert(client.clone());
+
+        assert!(registry.migrate(&client, new_address));
+        assert_eq!(client.get_address(), new_address);
+        assert!(registry
+            .get(&SocketAddr::from(([127, 0, 0, 1], 1)))
+            .is_none());
+        assert!(registry.get(&new_address).is_some());
+        assert_eq!(registry.clients_with_ip(new_address.ip()).len(), 1);
+    }
+
+    #[test]
+    fn should_not_migrate_to_a_taken_address() {
+        let registry = ClientRegistry::new();
+        let client = new_client(1);
+        let other_client = new_client(2);
+        registry.insert(client.clone());
+        registry.insert(other_client.clone());
+
+        assert!(!registry.migrate(&client, other_client.get_address()));
+        assert_eq!(client.get_address(), SocketAddr::from(([127, 0, 0, 1], 1)));
+    }
+
+    #[test]
+    fn should_not_remove_a_replaced_client() {
+        let registry = ClientRegistry::new();
+        let old_client = new_client(1);
+        let new_client = new_client(1);
+        registry.insert(old_client.clone());
+        registry.insert(new_client.clone());
+
+        assert!(!registry.remove_client(&old_client));
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.clients().len(), 1);
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum NotificationMethod {
+        Notify = 1,
+    }
+
+    impl From<NotificationMethod> for u32 {
+        fn from(method: NotificationMethod) -> Self {
+            method as u32
+        }
+    }
+
+    impl NexProtocol for NotificationMethod {
+        const PROTOCOL_ID: u8 = 14;
+    }
+
+    /// A server with online clients logged in as 1 and 2, where sends to 2 fail because its queue is full.
+    fn new_server_with_clients() -> TestServer {
+        let mut settings = ServerSettings::default();
+        settings.set_max_queued_bytes(150);
+        let server = TestServer::new(settings);
+
+        let mut client = new_client(1);
+        client.set_pid(1);
+        server.get_clients().insert(client);
+
+        let mut full_client = new_client(2);
+        full_client.set_pid(2);
+        let mut packet = full_client.new_data_packet(vec![0; 100]);
+        full_client
+            .queue_packet(&mut packet, 1300, 150)
+            .expect("Should have succeeded!");
+        server.get_clients().insert(full_client);
+
+        server
+    }
+
+    #[test]
+    fn should_find_connected_clients_by_pid() {
+        let server = new_server_with_clients();
+        assert!(server.find_client_by_pid(1).is_some());
+        assert!(server.find_client_by_pid(3).is_none());
+
+        let mut client = server
+            .find_client_by_pid(1)
+            .expect("Client should be indexed");
+        client.set_is_connected(false);
+        assert!(server.find_client_by_pid(1).is_none());
+        assert!(!server.is_pid_online(1));
+    }
+
+    #[tokio::test]
+    async fn should_send_data_to_online_pids() {
+        let server = new_server_with_clients();
+
+        assert_eq!(server.send_data_to_pid(1, vec![1, 2, 3]).await, Ok(()));
+        assert_eq!(
+            server.send_data_to_pid(3, vec![1, 2, 3]).await,
+            Err(crate::server::Error::PidNotConnected { pid: 3 })
+        );
+
+        // Offline pids are skipped quietly, while other errors are reported
+        let sent_pids = server.send_data_to_pids(&[1, 2, 3], vec![1, 2, 3]).await;
+        assert_eq!(sent_pids, vec![1]);
+
+        let errors = server.errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Send queue is full"));
+    }
+
+    #[tokio::test]
+    async fn should_send_rmc_requests_to_online_pids() {
+        let server = new_server_with_clients();
+
+        assert_eq!(
+            server
+                .send_rmc_request_to_pid(3, NotificationMethod::Notify, vec![])
+                .await,
+            Err(crate::server::Error::PidNotConnected { pid: 3 })
+        );
+
+        let sent_pids = server
+            .send_rmc_request_to_pids(&[1, 2, 3], NotificationMethod::Notify, vec![])
+            .await;
+        assert_eq!(sent_pids, vec![1]);
+
+        let errors = server.errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Send queue is full"));
+
+        let client = server
+            .find_client_by_pid(1)
+            .expect("Client should be indexed");
+        assert!(client.get_queued_bytes() > 0);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{crypto, packet::PacketType};
+use crate::{
+    crypto,
+    packet::{self, PacketType},
+};
 use snafu::Snafu;
 
 #[derive(Debug, PartialEq, Snafu)]
@@ -19,6 +22,27 @@ pub enum Error {
         sequence_id: u16,
         message: String,
     },
+    #[snafu(display(
+        "Packet error: {}",
+        error.to_string()
+    ))]
+    PacketError { error: packet::Error },
+    #[snafu(display(
+        "IO Error: {}",
+        error.to_string()
+    ))]
+    IoError { error: no_std_io::Error },
+    #[snafu(display(
+        "Send queue is full: 0x{:x} bytes queued and 0x{:x} more encoded, 0x{:x} allowed",
+        queued_bytes,
+        size,
+        max_queued_bytes
+    ))]
+    SendQueueFull {
+        queued_bytes: usize,
+        size: usize,
+        max_queued_bytes: usize,
+    },
     #[snafu(display("Error: {}", message))]
     Generic { message: String },
 }
@@ -29,6 +53,18 @@ impl From<crypto::Error> for Error {
     }
 }
 
+impl From<packet::Error> for Error {
+    fn from(error: packet::Error) -> Self {
+        Self::PacketError { error }
+    }
+}
+
+impl From<no_std_io::Error> for Error {
+    fn from(error: no_std_io::Error) -> Self {
+        Self::IoError { error }
+    }
+}
+
 impl From<&str> for Error {
     fn from(message: &str) -> Self {
         Self::Generic {
@@ -0,0 +1,426 @@
+use crate::counter::SequenceIdCounter;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+const INITIAL_WINDOW: f64 = 4.0;
+const MIN_WINDOW: f64 = 1.0;
+const MAX_WINDOW: f64 = 256.0;
+const INITIAL_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(200);
+const MAX_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many times a packet is resent before it's given up on.
+const MAX_RESENDS: u32 = 8;
+
+/// A congestion window in packets, grown by acks and shrunk by losses,
+/// with a round trip time estimate used for timeouts and pacing.
+#[derive(Debug, Clone)]
+pub struct CongestionWindow {
+    window: f64,
+    slow_start_threshold: f64,
+    smoothed_rtt: Option<Duration>,
+    rtt_variance: Duration,
+}
+
+impl Default for CongestionWindow {
+    fn default() -> Self {
+        Self {
+            window: INITIAL_WINDOW,
+            slow_start_threshold: MAX_WINDOW,
+            smoothed_rtt: None,
+            rtt_variance: Duration::ZERO,
+        }
+    }
+}
+
+impl CongestionWindow {
+    /// The number of unacknowledged packets allowed in flight.
+    pub fn window(&self) -> usize {
+        self.window as usize
+    }
+
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        self.smoothed_rtt
+    }
+
+    /// Grows the window, exponentially during slow start and linearly after.
+    /// `rtt` is only given for packets that weren't resent, since those samples are ambiguous.
+    pub fn on_ack(&mut self, rtt: Option<Duration>) {
+        if let Some(rtt) = rtt {
+            self.update_rtt(rtt);
+        }
+
+        let increase = if self.window < self.slow_start_threshold {
+            1.0
+        } else {
+            1.0 / self.window
+        };
+        self.window = (self.window + increase).min(MAX_WINDOW);
+    }
+
+    pub fn on_loss(&mut self) {
+        self.slow_start_threshold = (self.window / 2.0).max(MIN_WINDOW);
+        self.window = self.slow_start_threshold;
+    }
+
+    fn update_rtt(&mut self, rtt: Duration) {
+        match self.smoothed_rtt {
+            None => {
+                self.smoothed_rtt = Some(rtt);
+                self.rtt_variance = rtt / 2;
+            }
+            Some(smoothed_rtt) => {
+                let difference = if smoothed_rtt > rtt {
+                    smoothed_rtt - rtt
+                } else {
+                    rtt - smoothed_rtt
+                };
+                self.rtt_variance = (self.rtt_variance * 3 + difference) / 4;
+                self.smoothed_rtt = Some((smoothed_rtt * 7 + rtt) / 8);
+            }
+        }
+    }
+
+    pub fn retransmission_timeout(&self) -> Duration {
+        match self.smoothed_rtt {
+            Some(smoothed_rtt) => (smoothed_rtt + self.rtt_variance * 4)
+                .clamp(MIN_RETRANSMISSION_TIMEOUT, MAX_RETRANSMISSION_TIMEOUT),
+            None => INITIAL_RETRANSMISSION_TIMEOUT,
+        }
+    }
+
+    /// The time between new packets, which spreads a window's worth of packets over a round trip.
+    pub fn pacing_interval(&self) -> Duration {
+        match self.smoothed_rtt {
+            Some(smoothed_rtt) => smoothed_rtt / self.window().max(1) as u32,
+            None => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SendQueuePoll {
+    /// Send this datagram now.
+    Send(Vec<u8>),
+    /// Nothing can be sent until this much time passes, or the queue changes.
+    Wait(Duration),
+    /// Nothing is queued or waiting on an ack.
+    Idle,
+    /// The reliable packet with this sequence id was given up on after every resend,
+    /// so the client's reliable stream can't continue.
+    Failed(u16),
+}
+
+#[derive(Debug)]
+struct QueuedPacket {
+    sequence_id: u16,
+    data: Vec<u8>,
+    needs_ack: bool,
+}
+
+#[derive(Debug)]
+struct InFlightPacket {
+    sequence_id: u16,
+    data: Vec<u8>,
+    sent_at: Instant,
+    resends: u32,
+}
+
+/// Encoded packets waiting to be sent to a client, and reliable packets waiting on an ack.
+#[derive(Debug, Default)]
+pub struct SendQueue {
+    pending: VecDeque<QueuedPacket>,
+    in_flight: VecDeque<InFlightPacket>,
+    queued_bytes: usize,
+    congestion: CongestionWindow,
+    /// When the window last shrank. Packets sent before then were lost in the same loss event.
+    last_loss_at: Option<Instant>,
+    next_send_at: Option<Instant>,
+    is_sending: bool,
+}
+
+impl SendQueue {
+    /// Bytes that are either waiting to be sent or waiting on an ack.
+    pub fn queued_bytes(&self) -> usize {
+        self.queued_bytes
+    }
+
+    pub fn congestion(&self) -> &CongestionWindow {
+        &self.congestion
+    }
+
+    pub fn can_queue(&self, size: usize, max_queued_bytes: usize) -> bool {
+        self.queued_bytes + size <= max_queued_bytes
+    }
+
+    pub fn push(&mut self, sequence_id: u16, data: Vec<u8>, needs_ack: bool) {
+        self.queued_bytes += data.len();
+        self.pending.push_back(QueuedPacket {
+            sequence_id,
+            data,
+            needs_ack,
+        });
+    }
+
+    /// Claims the queue for sending.
+    /// Returns false if it's already being sent or there's nothing to send.
+    pub fn start_sending(&mut self) -> bool {
+        if self.is_sending || (self.pending.is_empty() && self.in_flight.is_empty()) {
+            return false;
+        }
+
+        self.is_sending = true;
+        true
+    }
+
+    /// Returns true if the sequence id was waiting on an ack.
+    pub fn acknowledge(&mut self, sequence_id: u16, now: Instant) -> bool {
+        let index = self
+            .in_flight
+            .iter()
+            .position(|packet| packet.sequence_id == sequence_id);
+
+        match index.and_then(|index| self.in_flight.remove(index)) {
+            Some(packet) => {
+                self.on_acknowledged(packet, now);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Acknowledges every packet up to and including `sequence_id`.
+    pub fn acknowledge_up_to(&mut self, sequence_id: u16, now: Instant) {
+        let (acknowledged, in_flight): (VecDeque<_>, VecDeque<_>) =
+            self.in_flight.drain(..).partition(|packet| {
+                packet.sequence_id == sequence_id
+                    || SequenceIdCounter::is_before(packet.sequence_id, sequence_id)
+            });
+
+        self.in_flight = in_flight;
+
+        for packet in acknowledged {
+            self.on_acknowledged(packet, now);
+        }
+    }
+
+    fn on_acknowledged(&mut self, packet: InFlightPacket, now: Instant) {
+        self.queued_bytes -= packet.data.len();
+        let rtt = (packet.resends == 0).then(|| now.saturating_duration_since(packet.sent_at));
+        self.congestion.on_ack(rtt);
+    }
+
+    /// Decides what to send next.
+    /// Resends of timed out packets go first, then new packets as the window and pacing allow.
+    /// Returning [SendQueuePoll::Idle] releases the claim from [SendQueue::start_sending].
+    pub fn poll(&mut self, now: Instant) -> SendQueuePoll {
+        let timeout = self.congestion.retransmission_timeout();
+
+        while let Some(packet) = self.in_flight.front_mut() {
+            if now.saturating_duration_since(packet.sent_at) < timeout {
+                break;
+            }
+
+            // A burst of timeouts is one loss event, so the window only shrinks once for it
+            if !self
+                .last_loss_at
+                .is_some_and(|last_loss_at| packet.sent_at < last_loss_at)
+            {
+                self.congestion.on_loss();
+                self.last_loss_at = Some(now);
+            }
+
+            if packet.resends >= MAX_RESENDS {
+                let sequence_id = packet.sequence_id;
+                if let Some(packet) = self.in_flight.pop_front() {
+                    self.queued_bytes -= packet.data.len();
+                }
+                return SendQueuePoll::Failed(sequence_id);
+            }
+
+            packet.resends += 1;
+            packet.sent_at = now;
+            let data = packet.data.clone();
+
+            // Keep the in flight packets ordered by when they were last sent
+            self.in_flight.rotate_left(1);
+            return SendQueuePoll::Send(data);
+        }
+
+        if !self.pending.is_empty() && self.in_flight.len() < self.congestion.window() {
+            if let Some(next_send_at) = self.next_send_at.filter(|time| *time > now) {
+                return SendQueuePoll::Wait(next_send_at - now);
+            }
+
+            if let Some(packet) = self.pending.pop_front() {
+                self.next_send_at = Some(now + self.congestion.pacing_interval());
+
+                if packet.needs_ack {
+                    self.in_flight.push_back(InFlightPacket {
+                        sequence_id: packet.sequence_id,
+                        data: packet.data.clone(),
+                        sent_at: now,
+                        resends: 0,
+                    });
+                } else {
+                    self.queued_bytes -= packet.data.len();
+                }
+
+                return SendQueuePoll::Send(packet.data);
+            }
+        }
+
+        if let Some(packet) = self.in_flight.front() {
+            let resend_at = packet.sent_at + timeout;
+            return SendQueuePoll::Wait(resend_at.saturating_duration_since(now));
+        }
+
+        self.is_sending = false;
+        SendQueuePoll::Idle
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.in_flight.clear();
+        self.queued_bytes = 0;
+        self.next_send_at = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn queue_packets(queue: &mut SendQueue, count: u16) {
+        for sequence_id in 1..=count {
+            queue.push(sequence_id, vec![sequence_id as u8], true);
+        }
+    }
+
+    #[test]
+    fn should_only_send_a_window_of_packets() {
+        let mut queue = SendQueue::default();
+        let now = Instant::now();
+        queue_packets(&mut queue, 10);
+        assert!(queue.start_sending());
+
+        for sequence_id in 1..=4 {
+            assert_eq!(queue.poll(now), SendQueuePoll::Send(vec![sequence_id]));
+        }
+
+        assert_eq!(
+            queue.poll(now),
+            SendQueuePoll::Wait(INITIAL_RETRANSMISSION_TIMEOUT)
+        );
+        assert!(queue.acknowledge(1, now));
+        assert_eq!(queue.poll(now), SendQueuePoll::Send(vec![5]));
+    }
+
+    #[test]
+    fn should_grow_the_window_on_acks() {
+        let mut queue = SendQueue::default();
+        let now = Instant::now();
+        queue_packets(&mut queue, 4);
+
+        while let SendQueuePoll::Send(_) = queue.poll(now) {}
+        queue.acknowledge_up_to(4, now + Duration::from_millis(100));
+
+        assert_eq!(queue.congestion().window(), 8);
+        assert_eq!(
+            queue.congestion().smoothed_rtt(),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(queue.queued_bytes(), 0);
+        assert_eq!(queue.poll(now), SendQueuePoll::Idle);
+    }
+
+    #[test]
+    fn should_pace_packets_by_rtt() {
+        let mut queue = SendQueue::default();
+        let now = Instant::now();
+        queue_packets(&mut queue, 1);
+        queue.poll(now);
+        queue.acknowledge(1, now + Duration::from_millis(50));
+
+        queue.push(2, vec![2], true);
+        queue.push(3, vec![3], true);
+        let now = now + Duration::from_millis(50);
+        assert_eq!(queue.poll(now), SendQueuePoll::Send(vec![2]));
+        assert_eq!(
+            queue.poll(now),
+            SendQueuePoll::Wait(Duration::from_millis(10))
+        );
+        assert_eq!(
+            queue.poll(now + Duration::from_millis(10)),
+            SendQueuePoll::Send(vec![3])
+        );
+    }
+
+    #[test]
+    fn should_resend_and_shrink_the_window_after_a_timeout() {
+        let mut queue = SendQueue::default();
+        let now = Instant::now();
+        queue_packets(&mut queue, 1);
+        assert_eq!(queue.poll(now), SendQueuePoll::Send(vec![1]));
+
+        let later = now + INITIAL_RETRANSMISSION_TIMEOUT;
+        assert_eq!(queue.poll(later), SendQueuePoll::Send(vec![1]));
+        assert_eq!(queue.congestion().window(), 2);
+    }
+
+    #[test]
+    fn should_shrink_the_window_once_per_loss_event() {
+        let mut queue = SendQueue::default();
+        let now = Instant::now();
+        queue_packets(&mut queue, 4);
+        while let SendQueuePoll::Send(_) = queue.poll(now) {}
+
+        let later = now + INITIAL_RETRANSMISSION_TIMEOUT;
+        for sequence_id in 1..=4 {
+            assert_eq!(queue.poll(later), SendQueuePoll::Send(vec![sequence_id]));
+        }
+        assert_eq!(queue.congestion().window(), 2);
+
+        // The resends timing out again is a new loss event
+        let later = later + INITIAL_RETRANSMISSION_TIMEOUT;
+        assert_eq!(queue.poll(later), SendQueuePoll::Send(vec![1]));
+        assert_eq!(queue.congestion().window(), 1);
+    }
+
+    #[test]
+    fn should_give_up_after_too_many_resends() {
+        let mut queue = SendQueue::default();
+        let mut now = Instant::now();
+        queue_packets(&mut queue, 1);
+        queue.poll(now);
+
+        for _ in 0..MAX_RESENDS {
+            now += MAX_RETRANSMISSION_TIMEOUT;
+            assert_eq!(queue.poll(now), SendQueuePoll::Send(vec![1]));
+        }
+
+        now += MAX_RETRANSMISSION_TIMEOUT;
+        assert_eq!(queue.poll(now), SendQueuePoll::Failed(1));
+        assert_eq!(queue.queued_bytes(), 0);
+        assert_eq!(queue.poll(now), SendQueuePoll::Idle);
+    }
+
+    #[test]
+    fn should_not_track_packets_without_acks() {
+        let mut queue = SendQueue::default();
+        let now = Instant::now();
+        queue.push(1, vec![1], false);
+        assert_eq!(queue.poll(now), SendQueuePoll::Send(vec![1]));
+        assert_eq!(queue.poll(now), SendQueuePoll::Idle);
+        assert_eq!(queue.queued_bytes(), 0);
+    }
+
+    #[test]
+    fn should_bound_queued_bytes() {
+        let mut queue = SendQueue::default();
+        queue.push(1, vec![0; 8], true);
+        assert!(queue.can_queue(2, 10));
+        assert!(!queue.can_queue(3, 10));
+    }
+}
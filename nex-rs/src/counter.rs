@@ -17,3 +17,58 @@ impl Counter {
         self.counter
     }
 }
+
+/// A counter for 16 bit packet sequence ids, which wraps back to 0 after 0xffff.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequenceIdCounter {
+    counter: u16,
+}
+
+impl SequenceIdCounter {
+    pub fn new(initial: u16) -> Self {
+        Self { counter: initial }
+    }
+
+    pub fn increment(&mut self) -> u16 {
+        self.counter = self.counter.wrapping_add(1);
+        self.counter
+    }
+
+    pub fn value(&self) -> u16 {
+        self.counter
+    }
+
+    /// Whether `sequence_id` comes before `other`, accounting for wraparound.
+    /// Ids up to half of the sequence space behind `other` are considered older.
+    pub fn is_before(sequence_id: u16, other: u16) -> bool {
+        (sequence_id.wrapping_sub(other) as i16) < 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_wrap_sequence_ids() {
+        let mut counter = SequenceIdCounter::new(0xfffe);
+        assert_eq!(counter.increment(), 0xffff);
+        assert_eq!(counter.increment(), 0);
+        assert_eq!(counter.increment(), 1);
+    }
+
+    #[test]
+    fn should_compare_sequence_ids() {
+        assert!(SequenceIdCounter::is_before(1, 2));
+        assert!(!SequenceIdCounter::is_before(2, 1));
+        assert!(!SequenceIdCounter::is_before(2, 2));
+    }
+
+    #[test]
+    fn should_compare_sequence_ids_across_rollover() {
+        assert!(SequenceIdCounter::is_before(0xffff, 0));
+        assert!(SequenceIdCounter::is_before(0xfff0, 0x10));
+        assert!(!SequenceIdCounter::is_before(0, 0xffff));
+        assert!(!SequenceIdCounter::is_before(0x10, 0xfff0));
+    }
+}
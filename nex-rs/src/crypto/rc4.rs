@@ -2,9 +2,12 @@ use super::result::CryptResult;
 use crypto::{
     buffer::{RefReadBuffer, RefWriteBuffer},
     rc4,
-    symmetriccipher::{Decryptor, Encryptor},
+    symmetriccipher::{Decryptor, Encryptor, SynchronousStreamCipher},
 };
 
+// RC4 can't read and write the same buffer, so in place processing goes through this much stack
+const IN_PLACE_CHUNK_SIZE: usize = 256;
+
 #[derive(Clone)]
 pub struct Rc4 {
     inner: rc4::Rc4,
@@ -34,4 +37,32 @@ impl Rc4 {
 
         Ok(out)
     }
+
+    /// Encrypts or decrypts data in place, without allocating.
+    pub fn apply_in_place(&mut self, data: &mut [u8]) {
+        let mut input = [0; IN_PLACE_CHUNK_SIZE];
+
+        for chunk in data.chunks_mut(IN_PLACE_CHUNK_SIZE) {
+            let input = &mut input[..chunk.len()];
+            input.copy_from_slice(chunk);
+            self.inner.process(input, chunk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_match_encrypt_when_applied_in_place() {
+        let data: Vec<u8> = (0..1000).map(|value| value as u8).collect();
+        let encrypted = Rc4::new(b"CD&ML")
+            .encrypt(&data)
+            .expect("Should have succeeded!");
+
+        let mut in_place = data;
+        Rc4::new(b"CD&ML").apply_in_place(&mut in_place);
+        assert_eq!(in_place, encrypted);
+    }
 }
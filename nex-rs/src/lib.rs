@@ -9,3 +9,4 @@ pub mod route;
 pub mod server;
 
 pub use macros;
+pub use snafu;
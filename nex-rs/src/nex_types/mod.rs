@@ -34,6 +34,9 @@ pub use null_data::*;
 mod result_code;
 pub use result_code::*;
 
+mod result_codes;
+pub use result_codes::*;
+
 mod result_range;
 pub use result_range::*;
 
@@ -1,9 +1,59 @@
-use core::mem;
+use super::ResultModule;
+use core::{fmt, mem};
 use no_std_io::{EndianRead, EndianWrite, Error, ReadOutput, Writer};
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+const ERROR_MASK: u32 = 1 << 31;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct ResultCode(u32);
 
+impl ResultCode {
+    pub const fn new_error(module_id: u16, code: u16) -> Self {
+        Self(ERROR_MASK | ((module_id as u32) << 16) | code as u32)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.0 & ERROR_MASK == 0
+    }
+
+    pub fn is_error(&self) -> bool {
+        !self.is_success()
+    }
+
+    pub fn module_id(&self) -> u16 {
+        ((self.0 & !ERROR_MASK) >> 16) as u16
+    }
+
+    pub fn code(&self) -> u16 {
+        self.0 as u16
+    }
+
+    pub fn module(&self) -> Option<ResultModule> {
+        ResultModule::from_id(self.module_id())
+    }
+
+    /// The name of a known code, such as `RendezVous::InvalidUsername`.
+    pub fn name(&self) -> Option<String> {
+        let module = self.module()?;
+        let code_name = module.code_name(self.code())?;
+        Some(format!("{}::{}", module.name(), code_name))
+    }
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.module() {
+            Some(module) => match module.code_name(self.code()) {
+                Some(code_name) => write!(f, "{}::{}", module.name(), code_name)?,
+                None => write!(f, "{}::{:#06X}", module.name(), self.code())?,
+            },
+            None => write!(f, "Unknown")?,
+        }
+
+        write!(f, " ({:#010X})", self.0)
+    }
+}
+
 impl EndianRead for ResultCode {
     fn try_read_le(bytes: &[u8]) -> Result<ReadOutput<Self>, Error> {
         let result = u32::try_read_le(bytes)?.into_other();
@@ -41,3 +91,48 @@ impl EndianWrite for ResultCode {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nex_types::{Authentication, Core, RendezVous};
+
+    #[test]
+    fn should_build_codes_from_the_registry() {
+        let result_code = ResultCode::from(RendezVous::InvalidUsername);
+        assert_eq!(u32::from(result_code), 0x80030064);
+        assert_eq!(result_code.module(), Some(ResultModule::RendezVous));
+        assert_eq!(result_code.module_id(), 0x0003);
+        assert_eq!(result_code.code(), 0x0064);
+        assert!(result_code.is_error());
+        assert_eq!(
+            ResultCode::from(0x8068000B),
+            Authentication::UnderMaintenance
+        );
+    }
+
+    #[test]
+    fn should_display_codes() {
+        assert_eq!(
+            ResultCode::from(RendezVous::InvalidUsername).to_string(),
+            "RendezVous::InvalidUsername (0x80030064)"
+        );
+        assert_eq!(
+            ResultCode::from(0x8001FFFF).to_string(),
+            "Core::0xFFFF (0x8001FFFF)"
+        );
+        assert_eq!(
+            ResultCode::from(0x80FF0001).to_string(),
+            "Unknown (0x80FF0001)"
+        );
+    }
+
+    #[test]
+    fn should_detect_success() {
+        let result_code = ResultCode::from(0x00010001);
+        assert!(result_code.is_success());
+        assert!(!result_code.is_error());
+        assert_eq!(result_code.module(), Some(ResultModule::Core));
+        assert_ne!(result_code, Core::Unknown);
+    }
+}
@@ -0,0 +1,319 @@
+use super::ResultCode;
+
+macro_rules! result_modules {
+    ($($module:ident = $module_id:literal { $($name:ident = $code:literal),* $(,)? }),* $(,)?) => {
+        /// The modules of well-known NEX result codes.
+        #[allow(clippy::upper_case_acronyms)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ResultModule {
+            $($module),*
+        }
+
+        impl ResultModule {
+            pub fn from_id(module_id: u16) -> Option<Self> {
+                match module_id {
+                    $($module_id => Some(Self::$module),)*
+                    _ => None,
+                }
+            }
+
+            pub fn id(&self) -> u16 {
+                match self {
+                    $(Self::$module => $module_id,)*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$module => stringify!($module),)*
+                }
+            }
+
+            /// The name of a code in this module, if it's a known one.
+            pub fn code_name(&self, code: u16) -> Option<&'static str> {
+                match self {
+                    $(Self::$module => match code {
+                        $($code => Some(stringify!($name)),)*
+                        _ => None,
+                    },)*
+                }
+            }
+        }
+
+        $(result_modules!(@codes $module = $module_id { $($name = $code),* });)*
+    };
+    (@codes $module:ident = $module_id:literal {}) => {};
+    (@codes $module:ident = $module_id:literal { $($name:ident = $code:literal),+ }) => {
+        #[allow(clippy::upper_case_acronyms)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[repr(u16)]
+        pub enum $module {
+            $($name = $code),+
+        }
+
+        impl From<$module> for ResultCode {
+            fn from(code: $module) -> Self {
+                ResultCode::new_error($module_id, code as u16)
+            }
+        }
+
+        impl PartialEq<$module> for ResultCode {
+            fn eq(&self, code: &$module) -> bool {
+                *self == ResultCode::from(*code)
+            }
+        }
+    };
+}
+
+// Modules without known codes are still listed so their codes display with a module name.
+result_modules! {
+    Core = 0x0001 {
+        Unknown = 0x0001,
+        NotImplemented = 0x0002,
+        InvalidPointer = 0x0003,
+        OperationAborted = 0x0004,
+        Exception = 0x0005,
+        AccessDenied = 0x0006,
+        InvalidHandle = 0x0007,
+        InvalidIndex = 0x0008,
+        OutOfMemory = 0x0009,
+        InvalidArgument = 0x000a,
+        Timeout = 0x000b,
+        InitializationFailure = 0x000c,
+        CallInitiationFailure = 0x000d,
+        RegistrationError = 0x000e,
+        BufferOverflow = 0x000f,
+        InvalidLockState = 0x0010,
+        InvalidSequence = 0x0011,
+        SystemError = 0x0012,
+        Cancelled = 0x0013,
+    },
+    DDL = 0x0002 {
+        InvalidSignature = 0x0001,
+        IncorrectVersion = 0x0002,
+    },
+    RendezVous = 0x0003 {
+        ConnectionFailure = 0x0001,
+        NotAuthenticated = 0x0002,
+        InvalidUsername = 0x0064,
+        InvalidPassword = 0x0065,
+        UsernameAlreadyExists = 0x0066,
+        AccountDisabled = 0x0067,
+        AccountExpired = 0x0068,
+        ConcurrentLoginDenied = 0x0069,
+        EncryptionFailure = 0x006a,
+        InvalidPID = 0x006b,
+        MaxConnectionsReached = 0x006c,
+        InvalidGID = 0x006d,
+        InvalidControlScriptID = 0x006e,
+        InvalidOperationInLiveEnvironment = 0x006f,
+        DuplicateEntry = 0x0070,
+        ControlScriptFailure = 0x0071,
+        ClassNotFound = 0x0072,
+        SessionVoid = 0x0073,
+        DDLMismatch = 0x0075,
+        InvalidConfiguration = 0x0076,
+        SessionFull = 0x00c8,
+        InvalidGatheringPassword = 0x00c9,
+        WithoutParticipationPeriod = 0x00ca,
+        PersistentGatheringCreationMax = 0x00cb,
+        PersistentGatheringParticipationMax = 0x00cc,
+        DeniedByParticipants = 0x00cd,
+        ParticipantInBlackList = 0x00ce,
+        GameServerMaintenance = 0x00cf,
+        OperationPostpone = 0x00d0,
+        OutOfRatingRange = 0x00d1,
+        ConnectionDisconnected = 0x00d2,
+        InvalidOperation = 0x00d3,
+        NotParticipatedGathering = 0x00d4,
+        MatchmakeSessionUserPasswordUnmatch = 0x00d5,
+        MatchmakeSessionSystemPasswordUnmatch = 0x00d6,
+        UserIsOffline = 0x00d7,
+        AlreadyParticipatedGathering = 0x00d8,
+        PermissionDenied = 0x00d9,
+        NotFriend = 0x00da,
+        SessionClosed = 0x00db,
+        DatabaseTemporarilyUnavailable = 0x00dc,
+        InvalidUniqueId = 0x00dd,
+        MatchmakingWithdrawn = 0x00de,
+        LimitExceeded = 0x00df,
+        AccountTemporarilyDisabled = 0x00e0,
+        PartiallyServiceClosed = 0x00e1,
+        ConnectionDisconnectedForConcurrentLogin = 0x00e2,
+    },
+    PythonCore = 0x0004 {
+        Exception = 0x0001,
+        TypeError = 0x0002,
+        IndexError = 0x0003,
+        InvalidReference = 0x0004,
+        CallFailure = 0x0005,
+        MemoryError = 0x0006,
+        KeyError = 0x0007,
+        OperationError = 0x0008,
+        ConversionError = 0x0009,
+        ValidationError = 0x000a,
+    },
+    Transport = 0x0005 {
+        Unknown = 0x0001,
+        ConnectionFailure = 0x0002,
+        InvalidUrl = 0x0003,
+        InvalidKey = 0x0004,
+        InvalidURLType = 0x0005,
+        DuplicateEndpoint = 0x0006,
+        IOError = 0x0007,
+        Timeout = 0x0008,
+        ConnectionReset = 0x0009,
+        IncorrectRemoteAuthentication = 0x000a,
+        ServerRequestError = 0x000b,
+        DecompressionFailure = 0x000c,
+        ReliableSendBufferFullFatal = 0x000d,
+        UPnPCannotInit = 0x000e,
+        UPnPCannotAddMapping = 0x000f,
+        NatPMPCannotInit = 0x0010,
+        NatPMPCannotAddMapping = 0x0011,
+        UnsupportedNAT = 0x0013,
+        DNSError = 0x0014,
+        ProxyError = 0x0015,
+        DataRemaining = 0x0016,
+        NoBuffer = 0x0017,
+        NotFound = 0x0018,
+        TemporaryServerError = 0x0019,
+        PermanentServerError = 0x001a,
+        ServiceUnavailable = 0x001b,
+        ReliableSendBufferFull = 0x001c,
+        InvalidStation = 0x001d,
+        InvalidSubStreamID = 0x001e,
+        PacketBufferFull = 0x001f,
+        NatTraversalError = 0x0020,
+        NatCheckError = 0x0021,
+    },
+    DOCore = 0x0006 {},
+    FPD = 0x0065 {
+        NotInitialized = 0x0000,
+        AlreadyInitialized = 0x0001,
+        NotConnected = 0x0002,
+        Connected = 0x0003,
+        InitializationFailure = 0x0004,
+        OutOfMemory = 0x0005,
+        RmcFailed = 0x0006,
+        InvalidArgument = 0x0007,
+        InvalidLocalAccountID = 0x0008,
+        InvalidPrincipalID = 0x0009,
+        InvalidLocalFriendCode = 0x000a,
+        LocalAccountNotExists = 0x000b,
+        LocalAccountNotLoaded = 0x000c,
+        LocalAccountAlreadyLoaded = 0x000d,
+        FriendAlreadyExists = 0x000e,
+        FriendNotExists = 0x000f,
+        FriendNumMax = 0x0010,
+        NotFriend = 0x0011,
+        FileIO = 0x0012,
+        P2PInternetProhibited = 0x0013,
+        Unknown = 0x0014,
+        InvalidState = 0x0015,
+        AddFriendProhibited = 0x0017,
+        InvalidAccount = 0x0019,
+        BlacklistedByMe = 0x001a,
+        FriendAlreadyAdded = 0x001c,
+        MyFriendListLimitExceed = 0x001d,
+        RequestLimitExceed = 0x001e,
+        InvalidMessageID = 0x001f,
+        MessageIsNotMine = 0x0020,
+        MessageIsNotForMe = 0x0021,
+        FriendRequestBlocked = 0x0022,
+        NotInMyFriendList = 0x0023,
+        FriendListedByMe = 0x0024,
+        NotInMyBlacklist = 0x0025,
+        IncompatibleAccount = 0x0026,
+        BlockSettingChangeNotAllowed = 0x0027,
+        SizeLimitExceeded = 0x0028,
+        OperationNotAllowed = 0x0029,
+        NotNetworkAccount = 0x002a,
+        NotificationNotFound = 0x002b,
+        PreferenceNotInitialized = 0x002c,
+        FriendRequestNotAllowed = 0x002d,
+    },
+    Authentication = 0x0068 {
+        NASAuthenticateError = 0x0001,
+        TokenParseError = 0x0002,
+        HttpConnectionError = 0x0003,
+        HttpDNSError = 0x0004,
+        HttpGetProxySetting = 0x0005,
+        TokenExpired = 0x0006,
+        ValidationFailed = 0x0007,
+        InvalidParam = 0x0008,
+        PrincipalIdUnmatched = 0x0009,
+        MoveCountUnmatch = 0x000a,
+        UnderMaintenance = 0x000b,
+        UnsupportedVersion = 0x000c,
+        ServerVersionIsOld = 0x000d,
+        Unknown = 0x000e,
+        ClientVersionIsOld = 0x000f,
+        AccountLibraryError = 0x0010,
+        ServiceNoLongerAvailable = 0x0011,
+        UnknownApplication = 0x0012,
+        ApplicationVersionIsOld = 0x0013,
+        OutOfService = 0x0014,
+        NetworkServiceLicenseRequired = 0x0015,
+        NetworkServiceLicenseSystemError = 0x0016,
+        NetworkServiceLicenseError3 = 0x0017,
+        NetworkServiceLicenseError4 = 0x0018,
+    },
+    Ranking = 0x0069 {
+        NotInitialized = 0x0001,
+        InvalidArgument = 0x0002,
+        RegistrationError = 0x0003,
+        NotFound = 0x0005,
+        InvalidScore = 0x0006,
+        InvalidDataSize = 0x0007,
+        PermissionDenied = 0x0009,
+        Unknown = 0x000a,
+        NotImplemented = 0x000b,
+    },
+    DataStore = 0x006a {
+        Unknown = 0x0001,
+        InvalidArgument = 0x0002,
+        PermissionDenied = 0x0003,
+        NotFound = 0x0004,
+        AlreadyLocked = 0x0005,
+        UnderReviewing = 0x0006,
+        Expired = 0x0007,
+        InvalidCheckToken = 0x0008,
+        SystemFileError = 0x0009,
+        OverCapacity = 0x000a,
+        OperationNotAllowed = 0x000b,
+        InvalidPassword = 0x000c,
+        ValueNotEqual = 0x000d,
+    },
+    ServiceItem = 0x006c {
+        Unknown = 0x0001,
+        InvalidArgument = 0x0002,
+        EShopUnknownHttpError = 0x0003,
+        EShopResponseParseError = 0x0004,
+        NotOwned = 0x0005,
+        InvalidLimitationType = 0x0006,
+        ConsumptionRightShortage = 0x0007,
+    },
+    MatchmakeReferee = 0x006f {
+        Unknown = 0x0001,
+        InvalidArgument = 0x0002,
+        AlreadyExists = 0x0003,
+        NotParticipatedGathering = 0x0004,
+        NotParticipatedRound = 0x0005,
+        StatsNotFound = 0x0006,
+        RoundNotFound = 0x0007,
+        RoundArbitrated = 0x0008,
+        RoundNotArbitrated = 0x0009,
+    },
+    Subscriber = 0x0070 {
+        Unknown = 0x0001,
+        InvalidArgument = 0x0002,
+        OverLimit = 0x0003,
+        PermissionDenied = 0x0004,
+    },
+    Ranking2 = 0x0071 {
+        Unknown = 0x0001,
+        InvalidArgument = 0x0002,
+        InvalidScore = 0x0003,
+    },
+}
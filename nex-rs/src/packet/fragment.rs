@@ -0,0 +1,158 @@
+use super::{Error, PacketResult};
+
+/// The largest fragment id in a run. Runs wrap back to 1 after this.
+pub const MAX_FRAGMENT_ID: u8 = u8::MAX;
+
+/// Splits a payload into fragments of at most `fragment_size` bytes.
+///
+/// Fragment ids count up from 1 and wrap back to 1 after [MAX_FRAGMENT_ID],
+/// so large payloads are sent as several fragment runs.
+/// The last fragment always has an id of 0, and an empty payload is sent as a single empty fragment.
+pub fn split_fragments(payload: &[u8], fragment_size: usize) -> PacketResult<Vec<(u8, &[u8])>> {
+    if fragment_size == 0 {
+        return Err(Error::InvalidFragmentSize { fragment_size });
+    }
+
+    if payload.is_empty() {
+        return Ok(vec![(0, payload)]);
+    }
+
+    let fragment_count = payload.len().div_ceil(fragment_size);
+    let fragments = payload
+        .chunks(fragment_size)
+        .enumerate()
+        .map(|(index, fragment)| {
+            let fragment_id = if index + 1 == fragment_count {
+                0
+            } else {
+                (index % MAX_FRAGMENT_ID as usize) as u8 + 1
+            };
+            (fragment_id, fragment)
+        })
+        .collect();
+
+    Ok(fragments)
+}
+
+/// Joins fragments back into a payload, in the order they were received.
+#[derive(Debug, Default)]
+pub struct FragmentAssembler {
+    expected_fragment_id: u8,
+    buffer: Vec<u8>,
+}
+
+impl FragmentAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a fragment, returning the full payload once the last fragment arrives.
+    pub fn push(&mut self, fragment_id: u8, fragment: &[u8]) -> PacketResult<Option<Vec<u8>>> {
+        let expected_fragment_id = self.expected_fragment_id % MAX_FRAGMENT_ID + 1;
+
+        if fragment_id != 0 && fragment_id != expected_fragment_id {
+            let found_fragment_id = fragment_id;
+            self.reset();
+            return Err(Error::UnexpectedFragment {
+                expected_fragment_id,
+                found_fragment_id,
+            });
+        }
+
+        self.buffer.extend_from_slice(fragment);
+
+        if fragment_id == 0 {
+            let payload = std::mem::take(&mut self.buffer);
+            self.reset();
+            return Ok(Some(payload));
+        }
+
+        self.expected_fragment_id = fragment_id;
+        Ok(None)
+    }
+
+    pub fn reset(&mut self) {
+        self.expected_fragment_id = 0;
+        self.buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reassemble(fragments: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut assembler = FragmentAssembler::new();
+        let (last, rest) = fragments.split_last().expect("Should have fragments");
+
+        for (fragment_id, fragment) in rest {
+            let result = assembler
+                .push(*fragment_id, fragment)
+                .expect("Should have succeeded!");
+            assert_eq!(result, None);
+        }
+
+        assembler
+            .push(last.0, last.1)
+            .expect("Should have succeeded!")
+            .expect("Should have a payload")
+    }
+
+    #[test]
+    fn should_send_small_payloads_in_one_fragment() {
+        let payload = [1, 2, 3];
+        let fragments = split_fragments(&payload, 10).expect("Should have succeeded!");
+        assert_eq!(fragments, vec![(0, payload.as_slice())]);
+    }
+
+    #[test]
+    fn should_send_empty_payloads_in_one_fragment() {
+        let fragments = split_fragments(&[], 10).expect("Should have succeeded!");
+        assert_eq!(fragments, vec![(0, [].as_slice())]);
+    }
+
+    #[test]
+    fn should_not_add_an_empty_fragment_for_exact_multiples() {
+        let payload = [1, 2, 3, 4, 5, 6];
+        let fragments = split_fragments(&payload, 3).expect("Should have succeeded!");
+        assert_eq!(
+            fragments,
+            vec![(1, [1, 2, 3].as_slice()), (0, [4, 5, 6].as_slice())]
+        );
+        assert_eq!(reassemble(&fragments), payload);
+    }
+
+    #[test]
+    fn should_wrap_fragment_ids_for_large_payloads() {
+        let payload: Vec<u8> = (0..600).map(|value| value as u8).collect();
+        let fragments = split_fragments(&payload, 1).expect("Should have succeeded!");
+
+        assert_eq!(fragments.len(), 600);
+        assert_eq!(fragments[254].0, 255);
+        assert_eq!(fragments[255].0, 1);
+        assert_eq!(fragments[510].0, 1);
+        assert_eq!(fragments[599].0, 0);
+        assert_eq!(reassemble(&fragments), payload);
+    }
+
+    #[test]
+    fn should_reject_a_zero_fragment_size() {
+        assert_eq!(
+            split_fragments(&[1], 0),
+            Err(Error::InvalidFragmentSize { fragment_size: 0 })
+        );
+    }
+
+    #[test]
+    fn should_reject_out_of_order_fragments() {
+        let mut assembler = FragmentAssembler::new();
+        assembler.push(1, &[1]).expect("Should have succeeded!");
+        assert_eq!(
+            assembler.push(3, &[3]),
+            Err(Error::UnexpectedFragment {
+                expected_fragment_id: 2,
+                found_fragment_id: 3,
+            })
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod fragment;
 mod packet_flag;
 mod packet_option;
 mod packet_type;
@@ -5,6 +6,7 @@ mod result;
 mod signature_context;
 mod v1;
 
+pub use fragment::*;
 pub use packet_flag::{PacketFlag, PacketFlags};
 pub use packet_option::PacketOption;
 pub use packet_type::PacketType;
@@ -12,13 +14,15 @@ pub use result::{Error, PacketResult};
 pub use signature_context::SignatureContext;
 pub use v1::PacketV1;
 
+use bytes::BytesMut;
+
 pub trait Packet {
     const VERSION: u8;
 
     const CLIENT_ID: u8 = 0xaf;
     const SERVER_ID: u8 = 0xa1;
 
-    fn to_bytes(&self, context: &SignatureContext) -> Vec<u8>;
+    fn to_bytes(&self, context: &SignatureContext) -> PacketResult<Vec<u8>>;
 
     fn get_source(&self) -> u8;
     fn set_source(&mut self, value: u8);
@@ -36,7 +40,7 @@ pub trait Packet {
     fn set_session_id(&mut self, value: u8);
 
     fn get_signature(&self) -> &[u8];
-    fn set_signature(&mut self, value: Vec<u8>);
+    fn set_signature(&mut self, value: [u8; 16]);
 
     fn get_sequence_id(&self) -> u16;
     fn set_sequence_id(&mut self, value: u16);
@@ -48,5 +52,5 @@ pub trait Packet {
     fn set_fragment_id(&mut self, value: u8);
 
     fn get_payload(&self) -> &[u8];
-    fn set_payload(&mut self, value: Vec<u8>);
+    fn set_payload(&mut self, value: impl Into<BytesMut>);
 }
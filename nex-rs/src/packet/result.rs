@@ -34,6 +34,21 @@ pub enum Error {
         packet_type: PacketType,
         sequence_id: u16,
     },
+    #[snafu(display("Options length 0x{:x} does not fit into a packet", size))]
+    OptionsTooLarge { size: usize },
+    #[snafu(display("Payload length 0x{:x} does not fit into a packet", size))]
+    PayloadTooLarge { size: usize },
+    #[snafu(display("Invalid fragment size 0x{:x}", fragment_size))]
+    InvalidFragmentSize { fragment_size: usize },
+    #[snafu(display(
+        "Unexpected fragment: wanted fragment_id 0x{:02x}, but received 0x{:02x}",
+        expected_fragment_id,
+        found_fragment_id
+    ))]
+    UnexpectedFragment {
+        expected_fragment_id: u8,
+        found_fragment_id: u8,
+    },
     #[snafu(display("Error reading or writing packet: {}", error))]
     IoError { error: no_std_io::Error },
 }
@@ -18,6 +18,12 @@ impl PacketV1Header {
         &self.raw
     }
 
+    /// The header without its magic, which is what packet signatures cover.
+    pub fn signature_bytes(&self) -> &[u8; HEADER_SIZE - 2] {
+        let [_, _, signature_bytes @ ..] = &self.raw;
+        signature_bytes
+    }
+
     pub fn flags(&self, flags_version: u32) -> PacketFlags {
         let shift = if flags_version == 0 { 3 } else { 4 };
         let flags = self.type_flags() >> shift;
@@ -135,7 +141,12 @@ impl Default for PacketV1Header {
 
 impl EndianRead for PacketV1Header {
     fn try_read_le(bytes: &[u8]) -> Result<ReadOutput<Self>, Error> {
-        let raw = bytes.read_byte_vec(0, HEADER_SIZE)?.try_into().unwrap();
+        let raw = bytes
+            .get(..HEADER_SIZE)
+            .and_then(|raw| raw.try_into().ok())
+            .ok_or(Error::InvalidRead {
+                message: "Not enough bytes for a packet header",
+            })?;
         let result = Self::new(raw);
         Ok(ReadOutput::new(result, HEADER_SIZE))
     }
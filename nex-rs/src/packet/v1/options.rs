@@ -36,23 +36,23 @@ impl PacketV1Options {
         }
     }
 
-    pub fn as_bytes(&self, packet_type: &PacketType) -> Vec<u8> {
+    pub fn as_bytes(&self, packet_type: &PacketType) -> Result<Vec<u8>, Error> {
         let mut stream = StreamContainer::new(vec![]);
 
         match packet_type {
             PacketType::Syn => {
-                stream.checked_write_stream_le(&self.syn_options());
+                stream.write_stream_le(&self.syn_options())?;
             }
             PacketType::Connect => {
-                stream.checked_write_stream_le(&self.connect_options());
+                stream.write_stream_le(&self.connect_options())?;
             }
             PacketType::Data => {
-                stream.checked_write_stream_le(&self.data_options());
+                stream.write_stream_le(&self.data_options())?;
             }
             _ => {}
         };
 
-        stream.into_raw()
+        Ok(stream.into_raw())
     }
 }
 
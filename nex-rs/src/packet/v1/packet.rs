@@ -2,60 +2,66 @@ use super::{header::PacketV1Header, options::PacketV1Options};
 use crate::packet::{
     Error, Packet, PacketFlag, PacketFlags, PacketResult, PacketType, SignatureContext,
 };
+use bytes::{Bytes, BytesMut};
 use hmac::{Hmac, Mac};
 use md5::Md5;
-use no_std_io::{Reader, StreamContainer, StreamReader, StreamWriter};
+use no_std_io::{EndianWrite, Reader, StreamContainer, StreamWriter};
+
+const SIGNATURE_SIZE: usize = 16;
+
+/// Checks that options and a payload of these sizes fit in a packet's header fields.
+fn check_sizes(options_size: usize, payload_size: usize) -> PacketResult<(u8, u16)> {
+    let options_len = options_size
+        .try_into()
+        .map_err(|_| Error::OptionsTooLarge { size: options_size })?;
+    let payload_size = payload_size
+        .try_into()
+        .map_err(|_| Error::PayloadTooLarge { size: payload_size })?;
+    Ok((options_len, payload_size))
+}
 
 #[derive(Debug, Default)]
 pub struct PacketV1 {
     header: PacketV1Header,
-    signature: Vec<u8>,
+    signature: [u8; SIGNATURE_SIZE],
     options: PacketV1Options,
-    payload: Vec<u8>,
+    payload: BytesMut,
 }
 
 impl Packet for PacketV1 {
     const VERSION: u8 = 1;
 
-    fn to_bytes(self: &PacketV1, context: &SignatureContext) -> Vec<u8> {
-        let raw_options = self.raw_options();
-        let options_len: u8 = raw_options
-            .len()
-            .try_into()
-            .expect("Options length is too large");
-        let payload_size: u16 = self
-            .payload
-            .len()
-            .try_into()
-            .expect("Payload length is too large");
+    fn to_bytes(self: &PacketV1, context: &SignatureContext) -> PacketResult<Vec<u8>> {
+        let raw_options = self.raw_options()?;
+        let (options_len, payload_size) = check_sizes(raw_options.len(), self.payload.len())?;
 
         let mut header = self.header;
         header.set_options_length(options_len);
         header.set_payload_size(payload_size);
 
-        let mut stream = StreamContainer::new(vec![]);
-        stream.checked_write_stream_le(&header);
+        let size = header.get_size() + SIGNATURE_SIZE + raw_options.len() + self.payload.len();
+        let mut stream = StreamContainer::new(Vec::with_capacity(size));
+        stream.write_stream_le(&header)?;
 
-        let raw_header = header.raw();
         let signature = Self::calculate_signature(
-            &raw_header[2..14].try_into().unwrap(),
+            header.signature_bytes(),
             &self.payload,
             context.client_connection_signature(),
             &raw_options,
             context,
         );
 
-        stream.checked_write_stream_bytes(&signature);
+        stream.write_stream_bytes(&signature)?;
 
         if options_len > 0 {
-            stream.checked_write_stream_bytes(&raw_options);
+            stream.write_stream_bytes(&raw_options)?;
         }
 
         if !self.payload.is_empty() {
-            stream.checked_write_stream_bytes(&self.payload);
+            stream.write_stream_bytes(&self.payload)?;
         }
 
-        stream.into_raw()
+        Ok(stream.into_raw())
     }
 
     fn get_source(&self) -> u8 {
@@ -96,7 +102,7 @@ impl Packet for PacketV1 {
     fn get_signature(&self) -> &[u8] {
         &self.signature
     }
-    fn set_signature(&mut self, value: Vec<u8>) {
+    fn set_signature(&mut self, value: [u8; SIGNATURE_SIZE]) {
         self.signature = value;
     }
 
@@ -124,8 +130,8 @@ impl Packet for PacketV1 {
     fn get_payload(&self) -> &[u8] {
         &self.payload
     }
-    fn set_payload(&mut self, value: Vec<u8>) {
-        self.payload = value;
+    fn set_payload(&mut self, value: impl Into<BytesMut>) {
+        self.payload = value.into();
     }
 }
 
@@ -152,20 +158,50 @@ impl PacketV1 {
         connection_signature: Vec<u8>,
         payload: Vec<u8>,
         flags_version: u32,
+    ) -> Self {
+        Self::new_data_packet_with_flags(
+            session_id,
+            connection_signature,
+            payload,
+            flags_version,
+            PacketFlag::Reliable | PacketFlag::NeedsAck | PacketFlag::HasSize,
+        )
+    }
+
+    /// Creates a data packet that isn't acknowledged or resent, and uses its own sequence ids.
+    pub fn new_unreliable_data_packet(
+        session_id: u8,
+        connection_signature: Vec<u8>,
+        payload: Vec<u8>,
+        flags_version: u32,
+    ) -> Self {
+        Self::new_data_packet_with_flags(
+            session_id,
+            connection_signature,
+            payload,
+            flags_version,
+            PacketFlags::new(PacketFlag::HasSize.into()),
+        )
+    }
+
+    fn new_data_packet_with_flags(
+        session_id: u8,
+        connection_signature: Vec<u8>,
+        payload: Vec<u8>,
+        flags_version: u32,
+        flags: PacketFlags,
     ) -> Self {
         let mut header = PacketV1Header::default();
         header.set_session_id(session_id);
         header.set_source(Self::SERVER_ID);
         header.set_destination(Self::CLIENT_ID);
         header.set_packet_type(flags_version, PacketType::Data);
-        header.set_flags(
-            flags_version,
-            PacketFlag::Reliable | PacketFlag::NeedsAck | PacketFlag::HasSize,
-        );
+        header.set_flags(flags_version, flags);
 
         Self {
             header,
-            payload,
+            // Takes over the payload's allocation instead of copying it
+            payload: BytesMut::from(Bytes::from(payload)),
             options: PacketV1Options {
                 connection_signature,
                 supported_functions: flags_version,
@@ -215,32 +251,78 @@ impl PacketV1 {
         }
     }
 
-    pub fn read_packet(data: Vec<u8>, flags_version: u32) -> PacketResult<Self> {
-        let mut packet = PacketV1::default();
+    /// Whether raw data is a SYN packet, whichever flags version it was written with.
+    /// SYN is the only packet type with none of the low 3 bits set, which both flags layouts share.
+    pub fn is_syn_packet(data: &[u8]) -> bool {
+        data.read_le::<PacketV1Header>(0)
+            .is_ok_and(|header| header.packet_type(0) == PacketType::Syn)
+    }
 
-        let mut stream = StreamContainer::new(data.as_slice());
+    /// Reads a packet without copying its payload when given a [BytesMut].
+    /// The payload is split off the same buffer, so it can be decrypted in place.
+    pub fn read_packet(data: impl Into<BytesMut>, flags_version: u32) -> PacketResult<Self> {
+        let mut data = data.into();
+        let mut packet = PacketV1::default();
 
-        packet.header = stream.read_stream_le::<PacketV1Header>()?;
-        packet.signature = stream.read_byte_stream(16)?;
+        packet.header = data[..].read_le::<PacketV1Header>(0)?;
 
+        let signature_start = packet.header.get_size();
+        let options_start = signature_start + SIGNATURE_SIZE;
         let options_len = usize::from(packet.header.options_length());
-        let raw_options = stream.read_byte_stream(options_len)?;
-        packet.options = raw_options.read_le(0)?;
+        let payload_start = options_start + options_len;
+        let payload_size = usize::from(packet.header.payload_size());
+        let payload_end = payload_start + payload_size;
+
+        if data.len() < payload_end {
+            return Err(Error::InvalidSize {
+                wanted_size: payload_end,
+                received_size: data.len(),
+                context: "packet payload",
+            });
+        }
+
+        packet
+            .signature
+            .copy_from_slice(&data[signature_start..options_start]);
+        packet.options = data[options_start..payload_start].read_le(0)?;
 
         let packet_type = packet.header.packet_type(flags_version);
         if packet_type != PacketType::Syn && packet_type != PacketType::Connect {
             packet.options.supported_functions = flags_version;
         }
 
-        let payload_size = usize::from(packet.header.payload_size());
-        packet.payload = stream.read_byte_stream(payload_size)?;
+        data.truncate(payload_end);
+        packet.payload = data.split_off(payload_start);
 
         Ok(packet)
     }
 
-    pub fn raw_options(&self) -> Vec<u8> {
-        self.options
-            .as_bytes(&self.header.packet_type(self.flags_version()))
+    pub fn get_mut_payload(&mut self) -> &mut [u8] {
+        &mut self.payload
+    }
+
+    /// Takes the payload, leaving the packet empty.
+    pub fn take_payload(&mut self) -> BytesMut {
+        std::mem::take(&mut self.payload)
+    }
+
+    /// The size [Packet::to_bytes] would encode this packet to with a payload of `payload_size` bytes.
+    /// Fails the same way it would if the options or payload don't fit in a packet.
+    pub fn encoded_size(&self, payload_size: usize) -> PacketResult<usize> {
+        let options_size = self.raw_options()?.len();
+        check_sizes(options_size, payload_size)?;
+        Ok(self.header.get_size() + SIGNATURE_SIZE + options_size + payload_size)
+    }
+
+    pub fn raw_options(&self) -> PacketResult<Vec<u8>> {
+        let raw_options = self
+            .options
+            .as_bytes(&self.header.packet_type(self.flags_version()))?;
+        Ok(raw_options)
+    }
+
+    pub fn is_unreliable_data(&self) -> bool {
+        self.get_packet_type() == PacketType::Data && !self.get_flags().reliable()
     }
 
     pub fn get_substream_id(&self) -> u8 {
@@ -275,6 +357,11 @@ impl PacketV1 {
     }
 
     pub fn validate(&self, context: &SignatureContext) -> PacketResult<()> {
+        self.validate_header()?;
+        self.validate_signature(context)
+    }
+
+    pub fn validate_header(&self) -> PacketResult<()> {
         let magic = self.header.magic();
         if magic != 0xd0ea {
             return Err(Error::InvalidMagic { magic });
@@ -285,22 +372,21 @@ impl PacketV1 {
             return Err(Error::InvalidVersion { version });
         }
 
-        self.validate_signature(context)
+        Ok(())
     }
 
     pub fn validate_signature(&self, context: &SignatureContext) -> PacketResult<()> {
-        let raw_header = self.header.raw();
         let calculated_signature = Self::calculate_signature(
-            &raw_header[2..14].try_into().unwrap(),
+            self.header.signature_bytes(),
             &self.payload,
             context.server_connection_signature(),
-            &self.raw_options(),
+            &self.raw_options()?,
             context,
         );
 
         if calculated_signature != self.signature {
             return Err(Error::InvalidSignature {
-                calculated_signature,
+                calculated_signature: calculated_signature.to_vec(),
                 found_signature: self.signature.to_vec(),
                 packet_type: self.get_packet_type(),
                 sequence_id: self.get_sequence_id(),
@@ -310,13 +396,25 @@ impl PacketV1 {
         Ok(())
     }
 
+    /// Finds which candidate access key signed this packet.
+    /// Meant for captured SYN packets, which are signed before any connection signature or session key exists.
+    pub fn find_access_key<T: AsRef<str>>(
+        &self,
+        candidates: impl IntoIterator<Item = T>,
+    ) -> Option<T> {
+        candidates.into_iter().find(|access_key| {
+            self.validate_signature(&SignatureContext::new(access_key.as_ref()))
+                .is_ok()
+        })
+    }
+
     pub fn calculate_signature(
         header: &[u8; 12],
         payload: &[u8],
         connection_signature: &[u8],
         options: &[u8],
         context: &SignatureContext,
-    ) -> Vec<u8> {
+    ) -> [u8; SIGNATURE_SIZE] {
         let key: &[u8; 16] = context.signature_key();
         let signature_base = context.signature_base();
 
@@ -328,7 +426,7 @@ impl PacketV1 {
         mac.update(connection_signature);
         mac.update(options);
         mac.update(payload);
-        mac.finalize().into_bytes().to_vec()
+        mac.finalize().into_bytes().into()
     }
 }
 
@@ -343,17 +441,64 @@ mod test {
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x01, 0x00,
     ];
 
+    #[test]
+    fn should_reject_sizes_that_do_not_fit_in_a_packet() {
+        assert_eq!(check_sizes(255, 0xffff), Ok((255, 0xffff)));
+        assert_eq!(
+            check_sizes(256, 0),
+            Err(Error::OptionsTooLarge { size: 256 })
+        );
+        assert_eq!(
+            check_sizes(0, 0x10000),
+            Err(Error::PayloadTooLarge { size: 0x10000 })
+        );
+    }
+
+    #[test]
+    fn should_know_its_encoded_size() {
+        let mut packet =
+            PacketV1::read_packet(BASE_PACKET.as_slice(), 1).expect("Should have succeeded!");
+        packet.set_payload(Bytes::from(vec![1, 2, 3]));
+
+        let encoded = packet
+            .to_bytes(&SignatureContext::default())
+            .expect("Should have succeeded!");
+        assert_eq!(packet.encoded_size(3), Ok(encoded.len()));
+        assert_eq!(
+            packet.encoded_size(0x10000),
+            Err(Error::PayloadTooLarge { size: 0x10000 })
+        );
+    }
+
     #[test]
     fn should_encode_and_decode() {
         let bytes = BASE_PACKET.to_vec();
         let flags_version = 1;
         let context = SignatureContext::default();
         let packet =
-            PacketV1::read_packet(bytes.clone(), flags_version).expect("Should have succeeded!");
-        let result = packet.to_bytes(&context);
+            PacketV1::read_packet(bytes.as_slice(), flags_version).expect("Should have succeeded!");
+        let result = packet.to_bytes(&context).expect("Should have succeeded!");
         assert_eq!(result, bytes);
     }
 
+    #[test]
+    fn should_find_the_access_key_that_signed_a_packet() {
+        let flags_version = 1;
+        let context = SignatureContext::new("ridfebb9");
+        let bytes = PacketV1::read_packet(BASE_PACKET.as_slice(), flags_version)
+            .expect("Should have succeeded!")
+            .to_bytes(&context)
+            .expect("Should have succeeded!");
+        let packet =
+            PacketV1::read_packet(bytes.as_slice(), flags_version).expect("Should have succeeded!");
+
+        assert_eq!(
+            packet.find_access_key(["6f599f81", "ridfebb9", "9f2b4678"]),
+            Some("ridfebb9")
+        );
+        assert_eq!(packet.find_access_key(["6f599f81", "9f2b4678"]), None);
+    }
+
     mod syn {
         use super::*;
 
@@ -368,8 +513,8 @@ mod test {
             ];
 
             let flags_version = 1;
-            let packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             assert_eq!(packet.get_packet_type(), PacketType::Syn);
             assert_eq!(packet.get_flags().needs_ack(), true);
@@ -387,14 +532,14 @@ mod test {
             let bytes = BASE_PACKET.to_vec();
             let flags_version = 4;
             let context = SignatureContext::default();
-            let mut packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let mut packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             packet.set_packet_type(PacketType::Syn);
             packet.set_flags(PacketFlag::NeedsAck | PacketFlag::HasSize);
             packet.options.maximum_substream_id = 1;
 
-            let result: Vec<u8> = packet.to_bytes(&context);
+            let result: Vec<u8> = packet.to_bytes(&context).expect("Should have succeeded!");
             let expected_result = vec![
                 0xea, 0xd0, 0x01, 0x1b, 0x00, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00,
                 0xf9, 0xc4, 0x5d, 0x51, 0xe8, 0xac, 0x68, 0x7a, 0x1b, 0xee, 0x01, 0x28, 0x9c, 0x9b,
@@ -420,8 +565,8 @@ mod test {
             ];
 
             let flags_version = 4;
-            let packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             assert_eq!(packet.get_packet_type(), PacketType::Connect);
             assert_eq!(packet.get_flags().reliable(), true);
@@ -443,17 +588,17 @@ mod test {
             let bytes = BASE_PACKET.to_vec();
             let flags_version = 4;
             let context = SignatureContext::default();
-            let mut packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let mut packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             packet.set_packet_type(PacketType::Connect);
             packet.set_flags(PacketFlag::Reliable | PacketFlag::NeedsAck | PacketFlag::HasSize);
             packet.set_session_id(1);
             packet.options.maximum_substream_id = 0;
             packet.options.initial_sequence_id = 0xabcd;
-            packet.payload = vec![0xaa];
+            packet.set_payload(&[0xaa][..]);
 
-            let result: Vec<u8> = packet.to_bytes(&context);
+            let result: Vec<u8> = packet.to_bytes(&context).expect("Should have succeeded!");
             let expected_result = vec![
                 0xea, 0xd0, 0x01, 0x1f, 0x01, 0x00, 0x00, 0x00, 0x71, 0x00, 0x01, 0x00, 0x00, 0x00,
                 0x69, 0x8c, 0xc3, 0xb2, 0x33, 0x99, 0x45, 0x4a, 0x2c, 0x70, 0x67, 0x0c, 0x3c, 0x4e,
@@ -478,8 +623,8 @@ mod test {
             ];
 
             let flags_version = 4;
-            let packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             assert_eq!(packet.get_packet_type(), PacketType::Data);
             assert_eq!(packet.get_flags().reliable(), true);
@@ -505,19 +650,21 @@ mod test {
             let bytes = BASE_PACKET.to_vec();
             let flags_version = 4;
             let context = SignatureContext::default();
-            let mut packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let mut packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             packet.set_packet_type(PacketType::Data);
             packet.set_flags(PacketFlag::Reliable | PacketFlag::NeedsAck | PacketFlag::HasSize);
             packet.set_session_id(1);
             packet.options.fragment_id = 0;
-            packet.payload = vec![
-                0x0d, 0x00, 0x00, 0x00, 0xaa, 0x01, 0x01, 0x01, 0x01, 0x02, 0x02, 0x02, 0x02, 0x03,
-                0x03, 0x03, 0x03,
-            ];
+            packet.set_payload(
+                &[
+                    0x0d, 0x00, 0x00, 0x00, 0xaa, 0x01, 0x01, 0x01, 0x01, 0x02, 0x02, 0x02, 0x02,
+                    0x03, 0x03, 0x03, 0x03,
+                ][..],
+            );
 
-            let result: Vec<u8> = packet.to_bytes(&context);
+            let result: Vec<u8> = packet.to_bytes(&context).expect("Should have succeeded!");
             let expected_result = vec![
                 0xea, 0xd0, 0x01, 0x03, 0x11, 0x00, 0x00, 0x00, 0x72, 0x00, 0x01, 0x00, 0x00, 0x00,
                 0x59, 0x7b, 0x00, 0xc5, 0xba, 0xd6, 0xf0, 0x0f, 0xab, 0xa3, 0x70, 0x5e, 0xdf, 0xeb,
@@ -539,8 +686,8 @@ mod test {
                 0x5b, 0x10,
             ];
             let flags_version = 4;
-            let packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             assert_eq!(packet.get_packet_type(), PacketType::Disconnect);
             assert_eq!(packet.get_flags().reliable(), true);
@@ -558,14 +705,14 @@ mod test {
             let bytes = BASE_PACKET.to_vec();
             let flags_version = 4;
             let context = SignatureContext::default();
-            let mut packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let mut packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             packet.set_packet_type(PacketType::Disconnect);
             packet.set_flags(PacketFlag::Reliable | PacketFlag::NeedsAck | PacketFlag::HasSize);
             packet.set_session_id(1);
 
-            let result: Vec<u8> = packet.to_bytes(&context);
+            let result: Vec<u8> = packet.to_bytes(&context).expect("Should have succeeded!");
             let expected_result = vec![
                 0xea, 0xd0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x73, 0x00, 0x01, 0x00, 0x00, 0x00,
                 0xdf, 0x11, 0xa0, 0xf7, 0x62, 0x06, 0xd4, 0x17, 0xbe, 0xee, 0xc9, 0x87, 0x93, 0x53,
@@ -586,8 +733,8 @@ mod test {
                 0x91, 0x2b,
             ];
             let flags_version = 4;
-            let packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             assert_eq!(packet.get_packet_type(), PacketType::Ping);
             assert_eq!(packet.get_flags().needs_ack(), true);
@@ -604,14 +751,14 @@ mod test {
             let bytes = BASE_PACKET.to_vec();
             let flags_version = 4;
             let context = SignatureContext::default();
-            let mut packet =
-                PacketV1::read_packet(bytes, flags_version).expect("Should have succeeded!");
+            let mut packet = PacketV1::read_packet(bytes.as_slice(), flags_version)
+                .expect("Should have succeeded!");
 
             packet.set_packet_type(PacketType::Ping);
             packet.set_flags(PacketFlag::NeedsAck | PacketFlag::HasSize);
             packet.set_session_id(1);
 
-            let result: Vec<u8> = packet.to_bytes(&context);
+            let result: Vec<u8> = packet.to_bytes(&context).expect("Should have succeeded!");
             let expected_result = vec![
                 0xea, 0xd0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, 0x00, 0x01, 0x00, 0x00, 0x00,
                 0x83, 0xd5, 0x78, 0x2e, 0x60, 0xe3, 0x45, 0xac, 0x40, 0xd9, 0x41, 0x3c, 0xee, 0x4d,
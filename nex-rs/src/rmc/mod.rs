@@ -1,3 +1,6 @@
+mod rmc_caller;
+pub use rmc_caller::*;
+
 mod rmc_request;
 pub use rmc_request::*;
 
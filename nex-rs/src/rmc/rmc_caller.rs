@@ -0,0 +1,90 @@
+use super::{RMCRequest, RMCResponse};
+use crate::{
+    nex_types::ResultCode,
+    result::Error,
+    route::{NexProtocol, EXTENDED_PROTOCOL_ID},
+    server,
+};
+use no_std_io::{EndianRead, EndianWrite, Reader, Writer};
+
+/// Sends RMC requests and returns their responses, such as over a test bot's connection to a server.
+/// Client calls generated by `nex_method` go through this.
+#[async_trait::async_trait]
+pub trait RMCCaller: Send {
+    fn next_call_id(&mut self) -> u32;
+
+    async fn call(&mut self, request: RMCRequest) -> Result<RMCResponse, Error>;
+
+    /// Calls a method with its already written parameters,
+    /// and returns its response data or the result code it failed with.
+    async fn call_with_parameters<Method>(
+        &mut self,
+        method: Method,
+        parameters: Vec<u8>,
+    ) -> Result<Result<Vec<u8>, ResultCode>, Error>
+    where
+        Method: NexProtocol + Into<u32> + Send,
+    {
+        let call_id = self.next_call_id();
+        let method_id = method.into();
+        let request = RMCRequest::new(Method::PROTOCOL_ID, method_id, call_id, parameters)
+            .with_custom_id(Method::CUSTOM_ID);
+        // Custom ids are only sent for the extended protocol id
+        let custom_id = if Method::PROTOCOL_ID == EXTENDED_PROTOCOL_ID {
+            Method::CUSTOM_ID
+        } else {
+            0
+        };
+        let response = self.call(request).await?;
+
+        if response.call_id() != call_id {
+            return Err(Error::Generic {
+                message: format!(
+                    "Expected a response to call {}, got {}",
+                    call_id,
+                    response.call_id()
+                ),
+            });
+        }
+
+        // Error responses don't include a method id
+        if response.protocol_id() != Method::PROTOCOL_ID
+            || response.custom_id() != custom_id
+            || response.method_id().is_some_and(|id| id != method_id)
+        {
+            return Err(Error::Generic {
+                message: format!(
+                    "Expected a response to protocol {} custom id {} method {}, got protocol {} custom id {} method {:?}",
+                    Method::PROTOCOL_ID,
+                    custom_id,
+                    method_id,
+                    response.protocol_id(),
+                    response.custom_id(),
+                    response.method_id()
+                ),
+            });
+        }
+
+        Ok(response.into_result())
+    }
+
+    /// Calls a method with its input, and reads its output or the result code it failed with.
+    async fn call_method<Method, Input, Output>(
+        &mut self,
+        method: Method,
+        input: &Input,
+    ) -> Result<Result<Output, ResultCode>, Error>
+    where
+        Method: NexProtocol + Into<u32> + Send,
+        Input: EndianWrite + Sync,
+        Output: EndianRead + Send,
+    {
+        let mut parameters = vec![];
+        parameters.write_le(0, input).map_err(server::Error::from)?;
+
+        match self.call_with_parameters(method, parameters).await? {
+            Ok(data) => Ok(Ok(data.read_le(0).map_err(server::Error::from)?)),
+            Err(error_code) => Ok(Err(error_code)),
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use crate::route::NexProtocol;
+use crate::route::{NexProtocol, EXTENDED_PROTOCOL_ID};
 use no_std_io::{
-    Cursor, EndianRead, EndianWrite, Error, ReadOutput, StreamContainer, StreamReader, StreamWriter,
+    Cursor, EndianRead, EndianWrite, Error, ReadOutput, StreamContainer, StreamReader,
+    StreamWriter, Writer,
 };
 
 #[derive(Default, Debug)]
@@ -13,8 +14,38 @@ pub struct RMCRequest {
 }
 
 impl RMCRequest {
+    pub fn new(
+        protocol_id: u8,
+        method_id: impl Into<u32>,
+        call_id: u32,
+        parameters: Vec<u8>,
+    ) -> Self {
+        Self {
+            protocol_id,
+            call_id,
+            method_id: method_id.into(),
+            parameters,
+            custom_id: 0,
+        }
+    }
+
+    pub fn with_custom_id(mut self, custom_id: u16) -> Self {
+        self.custom_id = custom_id;
+        self
+    }
+
+    pub fn is_protocol<T: NexProtocol>(&self) -> bool {
+        self.is_protocol_id(T::PROTOCOL_ID, T::CUSTOM_ID)
+    }
+
+    /// Custom ids are only compared for the extended protocol id, since other protocols don't send one.
+    pub fn is_protocol_id(&self, protocol_id: u8, custom_id: u16) -> bool {
+        self.protocol_id == protocol_id
+            && (protocol_id != EXTENDED_PROTOCOL_ID || self.custom_id == custom_id)
+    }
+
     pub fn is_method<T: NexProtocol + Into<u32>>(&self, method: T) -> bool {
-        self.protocol_id == T::PROTOCOL_ID && self.method_id == method.into()
+        self.is_protocol::<T>() && self.method_id == method.into()
     }
 }
 
@@ -44,13 +75,17 @@ impl EndianRead for RMCRequest {
         }
 
         let protocol_id = stream.read_stream_le::<u8>()? ^ 0x80;
-        let custom_id = if protocol_id == 0x7f {
+        let custom_id = if protocol_id == EXTENDED_PROTOCOL_ID {
             stream.read_stream_le()?
         } else {
             0
         };
 
-        let base = if protocol_id == 0x7f { 15 } else { 13 };
+        let base = if protocol_id == EXTENDED_PROTOCOL_ID {
+            15
+        } else {
+            13
+        };
 
         let rmc_request = Self {
             protocol_id,
@@ -70,8 +105,12 @@ impl EndianRead for RMCRequest {
 
 impl EndianWrite for RMCRequest {
     fn get_size(&self) -> usize {
-        // 17 is when including custom id
-        let base = if self.protocol_id == 0x7f { 17 } else { 13 };
+        // 15 is when including custom id
+        let base = if self.protocol_id == EXTENDED_PROTOCOL_ID {
+            15
+        } else {
+            13
+        };
 
         self.parameters.len() + base
     }
@@ -87,7 +126,7 @@ impl EndianWrite for RMCRequest {
         stream.write_stream_le(&data_size)?;
         stream.write_stream_le(&(self.protocol_id | 0x80))?;
 
-        if self.protocol_id == 0x7f {
+        if self.protocol_id == EXTENDED_PROTOCOL_ID {
             stream.write_stream_le(&self.custom_id)?;
         }
 
@@ -102,3 +141,34 @@ impl EndianWrite for RMCRequest {
         unimplemented!()
     }
 }
+
+impl TryFrom<RMCRequest> for Vec<u8> {
+    type Error = no_std_io::Error;
+
+    fn try_from(request: RMCRequest) -> Result<Self, Self::Error> {
+        let mut result: Vec<u8> = Vec::with_capacity(request.get_size());
+        result.write_le(0, &request)?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use no_std_io::Reader;
+
+    #[test]
+    fn should_encode_and_decode_extended_protocol_requests() {
+        let request =
+            RMCRequest::new(EXTENDED_PROTOCOL_ID, 2u32, 3, vec![0xaa, 0xbb]).with_custom_id(0x1234);
+        let bytes: Vec<u8> = request.try_into().expect("Should have succeeded!");
+        assert_eq!(bytes.len(), 17);
+
+        let result: RMCRequest = bytes.read_le(0).expect("Should have succeeded!");
+        assert_eq!(result.protocol_id, EXTENDED_PROTOCOL_ID);
+        assert_eq!(result.custom_id, 0x1234);
+        assert_eq!(result.call_id, 3);
+        assert_eq!(result.method_id, 2);
+        assert_eq!(result.parameters, vec![0xaa, 0xbb]);
+    }
+}
@@ -1,19 +1,31 @@
+use crate::{nex_types::ResultCode, route::EXTENDED_PROTOCOL_ID};
 use no_std_io::{
-    Cursor, EndianRead, EndianWrite, Error, ReadOutput, StreamContainer, StreamReader,
+    Cursor, EndianRead, EndianWrite, Error, ReadOutput, Reader, StreamContainer, StreamReader,
     StreamWriter, Writer,
 };
 
 const ERROR_MASK: u32 = 1 << 31;
+const RESPONSE_METHOD_MASK: u32 = 0x8000;
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct RMCResponse {
     protocol_id: u8,
     custom_id: u16,
-    is_success: bool,
     call_id: u32,
-    method_id: u32,
-    data: Vec<u8>,
-    error_code: u32,
+    method_id: Option<u32>,
+    result: Result<Vec<u8>, ResultCode>,
+}
+
+impl Default for RMCResponse {
+    fn default() -> Self {
+        Self {
+            protocol_id: 0,
+            custom_id: 0,
+            call_id: 0,
+            method_id: Some(0),
+            result: Ok(vec![]),
+        }
+    }
 }
 
 impl RMCResponse {
@@ -24,13 +36,11 @@ impl RMCResponse {
         data: Vec<u8>,
     ) -> Self {
         Self {
-            data,
             protocol_id,
-            method_id: method_id.into(),
-            call_id,
-            is_success: true,
-            error_code: 0,
             custom_id: 0,
+            call_id,
+            method_id: Some(method_id.into()),
+            result: Ok(data),
         }
     }
 
@@ -40,22 +50,52 @@ impl RMCResponse {
         call_id: u32,
         error_code: u32,
     ) -> Self {
-        let fixed_error_code = if error_code & ERROR_MASK == 0 {
-            error_code | ERROR_MASK
-        } else {
-            error_code
-        };
-
         Self {
             protocol_id,
-            call_id,
-            method_id: method_id.into(),
-            error_code: fixed_error_code,
-            data: vec![],
-            is_success: false,
             custom_id: 0,
+            call_id,
+            method_id: Some(method_id.into()),
+            result: Err((error_code | ERROR_MASK).into()),
         }
     }
+
+    pub fn with_custom_id(mut self, custom_id: u16) -> Self {
+        self.custom_id = custom_id;
+        self
+    }
+
+    pub fn protocol_id(&self) -> u8 {
+        self.protocol_id
+    }
+
+    pub fn custom_id(&self) -> u16 {
+        self.custom_id
+    }
+
+    pub fn call_id(&self) -> u32 {
+        self.call_id
+    }
+
+    /// Error responses don't include a method id, so this is `None` for errors that were read.
+    pub fn method_id(&self) -> Option<u32> {
+        self.method_id
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    pub fn data(&self) -> Option<&[u8]> {
+        self.result.as_deref().ok()
+    }
+
+    pub fn error_code(&self) -> Option<ResultCode> {
+        self.result.as_ref().err().copied()
+    }
+
+    pub fn into_result(self) -> Result<Vec<u8>, ResultCode> {
+        self.result
+    }
 }
 
 impl EndianRead for RMCResponse {
@@ -84,7 +124,7 @@ impl EndianRead for RMCResponse {
         }
 
         let protocol_id = stream.read_stream_le::<u8>()?;
-        let custom_id = if protocol_id == 0x7f {
+        let custom_id = if protocol_id == EXTENDED_PROTOCOL_ID {
             stream.read_stream_le()?
         } else {
             0
@@ -92,27 +132,33 @@ impl EndianRead for RMCResponse {
 
         let is_success = stream.read_stream_le()?;
 
-        let base = if protocol_id == 0x7f { 16 } else { 14 };
+        let base = if protocol_id == EXTENDED_PROTOCOL_ID {
+            16
+        } else {
+            14
+        };
 
         let rmc_response = if is_success {
+            let call_id = stream.read_stream_le()?;
+            let method_id = stream.read_stream_le::<u32>()? & !RESPONSE_METHOD_MASK;
+            let data = stream.read_byte_stream(bytes_len.saturating_sub(base))?;
+
             Self {
                 protocol_id,
                 custom_id,
-                is_success,
-                call_id: stream.read_stream_le()?,
-                method_id: stream.read_stream_le()?,
-                data: stream.default_read_byte_stream(bytes_len - base),
-                error_code: 0,
+                call_id,
+                method_id: Some(method_id),
+                result: Ok(data),
             }
         } else {
+            let error_code = stream.read_stream_le()?;
+
             Self {
                 protocol_id,
                 custom_id,
-                is_success,
-                error_code: stream.read_stream_le()?,
                 call_id: stream.read_stream_le()?,
-                method_id: 0,
-                data: vec![],
+                method_id: None,
+                result: Err(error_code),
             }
         };
         Ok(ReadOutput::new(rmc_response, stream.get_index()))
@@ -125,14 +171,19 @@ impl EndianRead for RMCResponse {
 
 impl EndianWrite for RMCResponse {
     fn get_size(&self) -> usize {
-        // 16 is when including custom id
-        let mut base = if self.protocol_id == 0x7f { 16 } else { 14 };
+        // Size, protocol id, custom id and success
+        let header_size = if self.protocol_id == EXTENDED_PROTOCOL_ID {
+            8
+        } else {
+            6
+        };
 
-        if self.is_success {
-            base += 8 + self.data.len();
+        match &self.result {
+            // Call id, method id and data
+            Ok(data) => header_size + 8 + data.len(),
+            // Error code and call id
+            Err(_) => header_size + 8,
         }
-
-        base
     }
 
     fn try_write_le(&self, dst: &mut [u8]) -> Result<usize, Error> {
@@ -140,25 +191,29 @@ impl EndianWrite for RMCResponse {
         let data_size: u32 = (self.get_size() - 4)
             .try_into()
             .map_err(|_| Error::InvalidWrite {
-                message: "RMCRequest size does not fit into u32",
+                message: "RMCResponse size does not fit into u32",
             })?;
 
         stream.write_stream_le(&data_size)?;
         stream.write_stream_le(&self.protocol_id)?;
 
-        if self.protocol_id == 0x7f {
+        if self.protocol_id == EXTENDED_PROTOCOL_ID {
             stream.write_stream_le(&self.custom_id)?;
         }
 
-        stream.write_stream_le(&self.is_success)?;
+        stream.write_stream_le(&self.is_success())?;
 
-        if self.is_success {
-            stream.write_stream_le(&self.call_id)?;
-            stream.write_stream_le(&(self.method_id | 0x8000))?;
-            stream.write_stream_bytes(&self.data)?;
-        } else {
-            stream.write_stream_le(&self.error_code)?;
-            stream.write_stream_le(&self.call_id)?;
+        match &self.result {
+            Ok(data) => {
+                let method_id = self.method_id.unwrap_or_default() | RESPONSE_METHOD_MASK;
+                stream.write_stream_le(&self.call_id)?;
+                stream.write_stream_le(&method_id)?;
+                stream.write_stream_bytes(data)?;
+            }
+            Err(error_code) => {
+                stream.write_stream_le(error_code)?;
+                stream.write_stream_le(&self.call_id)?;
+            }
         }
 
         Ok(stream.get_index())
@@ -169,10 +224,87 @@ impl EndianWrite for RMCResponse {
     }
 }
 
-impl From<RMCResponse> for Vec<u8> {
-    fn from(response: RMCResponse) -> Self {
+impl TryFrom<RMCResponse> for Vec<u8> {
+    type Error = no_std_io::Error;
+
+    fn try_from(response: RMCResponse) -> Result<Self, Self::Error> {
         let mut result: Vec<u8> = Vec::with_capacity(response.get_size());
-        result.checked_write_le(0, &response);
-        result
+        result.write_le(0, &response)?;
+        Ok(result)
+    }
+}
+
+/// Reads the success data as `T`, or returns the error's result code.
+/// Fails if the success data can't be read as `T`.
+impl<T: EndianRead> TryFrom<RMCResponse> for Result<T, ResultCode> {
+    type Error = no_std_io::Error;
+
+    fn try_from(response: RMCResponse) -> Result<Self, Self::Error> {
+        match response.result {
+            Ok(data) => Ok(Ok(data.read_le(0)?)),
+            Err(error_code) => Ok(Err(error_code)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(response: RMCResponse) -> RMCResponse {
+        let bytes: Vec<u8> = response.try_into().expect("Should have succeeded!");
+        bytes.read_le(0).expect("Should have succeeded!")
+    }
+
+    #[test]
+    fn should_encode_and_decode_success_responses() {
+        let response = RMCResponse::new_success(0x0a, 2u32, 3, vec![0xaa, 0xbb]);
+        let bytes: Vec<u8> = response.try_into().expect("Should have succeeded!");
+        assert_eq!(
+            bytes,
+            vec![
+                0x0c, 0x00, 0x00, 0x00, 0x0a, 0x01, 0x03, 0x00, 0x00, 0x00, 0x02, 0x80, 0x00, 0x00,
+                0xaa, 0xbb
+            ]
+        );
+
+        let result: RMCResponse = bytes.read_le(0).expect("Should have succeeded!");
+        assert_eq!(result.protocol_id(), 0x0a);
+        assert_eq!(result.call_id(), 3);
+        assert_eq!(result.method_id(), Some(2));
+        assert_eq!(result.data(), Some([0xaa, 0xbb].as_slice()));
+        assert_eq!(result.error_code(), None);
+    }
+
+    #[test]
+    fn should_encode_and_decode_error_responses() {
+        let result = round_trip(RMCResponse::new_error(0x0a, 2u32, 3, 0x00010001));
+        assert!(!result.is_success());
+        assert_eq!(result.call_id(), 3);
+        assert_eq!(result.method_id(), None);
+        assert_eq!(result.data(), None);
+        assert_eq!(result.error_code(), Some(0x80010001.into()));
+    }
+
+    #[test]
+    fn should_encode_and_decode_extended_protocol_responses() {
+        let result = round_trip(
+            RMCResponse::new_success(EXTENDED_PROTOCOL_ID, 2u32, 3, vec![0xaa])
+                .with_custom_id(0x1234),
+        );
+        assert_eq!(result.protocol_id(), EXTENDED_PROTOCOL_ID);
+        assert_eq!(result.custom_id(), 0x1234);
+        assert_eq!(result.data(), Some([0xaa].as_slice()));
+    }
+
+    #[test]
+    fn should_convert_into_a_typed_result() {
+        let success = round_trip(RMCResponse::new_success(0x0a, 2u32, 3, vec![1, 0, 0, 0]));
+        let result: Result<u32, ResultCode> = success.try_into().expect("Should have succeeded!");
+        assert_eq!(result, Ok(1));
+
+        let error = round_trip(RMCResponse::new_error(0x0a, 2u32, 3, 0x00010001));
+        let result: Result<u32, ResultCode> = error.try_into().expect("Should have succeeded!");
+        assert_eq!(result, Err(0x80010001.into()));
     }
 }
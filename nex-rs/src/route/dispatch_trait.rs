@@ -0,0 +1,14 @@
+use super::NexProtocol;
+use crate::{client::ClientConnection, rmc::RMCRequest, server::ServerResult};
+
+/// Sends a protocol's requests to their routes, answering unrouted methods with Core::NotImplemented.
+/// The server's middleware runs once around each request, routed or not.
+/// `#[nex_protocol]` implements this for the server it's used on.
+#[async_trait::async_trait]
+pub trait Dispatch<Protocol: NexProtocol> {
+    async fn dispatch(
+        &self,
+        client: &mut ClientConnection,
+        request: &RMCRequest,
+    ) -> ServerResult<()>;
+}
@@ -1,3 +1,6 @@
+mod dispatch_trait;
+pub use dispatch_trait::*;
+
 mod nex_protocol;
 pub use nex_protocol::*;
 
@@ -1,3 +1,8 @@
+/// Protocols past the one byte id range use this protocol id, followed by their custom id.
+pub const EXTENDED_PROTOCOL_ID: u8 = 0x7f;
+
 pub trait NexProtocol {
     const PROTOCOL_ID: u8;
+    /// Only used when [Self::PROTOCOL_ID] is [EXTENDED_PROTOCOL_ID].
+    const CUSTOM_ID: u16 = 0;
 }
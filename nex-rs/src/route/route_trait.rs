@@ -1,6 +1,16 @@
-use crate::{client::ClientConnection, rmc::RMCRequest, server::ServerResult};
+use crate::{
+    client::ClientConnection, nex_types::ResultCode, rmc::RMCRequest, server::ServerResult,
+};
 
 #[async_trait::async_trait]
-pub trait Route<const PROTOCOL_ID: u8, const METHOD_ID: u32> {
+pub trait Route<const PROTOCOL_ID: u8, const METHOD_ID: u32, const CUSTOM_ID: u16 = 0> {
+    /// Answers the request without running middleware, returning what it was answered with.
+    async fn respond(
+        &self,
+        client: &mut ClientConnection,
+        request: &RMCRequest,
+    ) -> ServerResult<Result<(), ResultCode>>;
+
+    /// Runs the server's middleware around [Route::respond].
     async fn run(&self, client: &mut ClientConnection, request: &RMCRequest) -> ServerResult<()>;
 }
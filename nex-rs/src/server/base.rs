@@ -1,17 +1,29 @@
-use super::ServerSettings;
-use crate::{client::ClientConnection, counter::Counter};
+use super::{MiddlewareChain, MigrationLimiter, ServerMetrics, ServerSettings};
+use crate::client::{ClientConnection, ClientRegistry};
 use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
-use tokio::{net::UdpSocket, sync::RwLock, task::JoinHandle};
+use tokio::{
+    net::UdpSocket,
+    sync::{mpsc::UnboundedSender, RwLock},
+    task::JoinHandle,
+};
 
+/// The largest datagram a receive buffer can hold.
+pub const MAX_DATAGRAM_SIZE: usize = 0x1000;
+
+#[deprecated(note = "Clients are stored in a ClientRegistry, see Server::get_clients")]
 pub type ClientMap = BTreeMap<SocketAddr, RwLock<ClientConnection>>;
 
 #[derive(Default)]
 pub struct BaseServer {
-    pub connection_id_counter: Counter,
     pub(super) settings: ServerSettings,
     pub(super) socket: Option<UdpSocket>,
+    pub(super) reuse_port_sockets: Vec<UdpSocket>,
     pub(super) ping_kick_thread: Option<JoinHandle<()>>,
-    pub(super) clients: Arc<RwLock<ClientMap>>,
+    pub(super) clients: Arc<ClientRegistry>,
+    pub(super) metrics: ServerMetrics,
+    pub(super) middleware: MiddlewareChain,
+    pub(super) migration_limiter: MigrationLimiter,
+    pub(super) send_pump: Option<UnboundedSender<ClientConnection>>,
 }
 
 impl BaseServer {
@@ -19,9 +31,13 @@ impl BaseServer {
         Self {
             settings,
             socket: None,
-            connection_id_counter: Counter::new(10),
+            reuse_port_sockets: vec![],
             ping_kick_thread: None,
-            clients: Arc::new(RwLock::new(BTreeMap::new())),
+            clients: Arc::new(ClientRegistry::new()),
+            metrics: ServerMetrics::default(),
+            middleware: MiddlewareChain::default(),
+            migration_limiter: MigrationLimiter::default(),
+            send_pump: None,
         }
     }
 }
@@ -0,0 +1,269 @@
+use super::{RecvBufferPool, MAX_DATAGRAM_SIZE};
+use bytes::BytesMut;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{io, mem, net::SocketAddr, os::unix::io::AsRawFd, ptr};
+use tokio::{
+    io::Interest,
+    net::{lookup_host, UdpSocket},
+};
+
+/// Receive buffers for one socket, reused across `recvmmsg` calls.
+pub struct RecvBatch {
+    batch_size: usize,
+    buffers: RecvBufferPool,
+    addresses: Vec<libc::sockaddr_storage>,
+    address_lengths: Vec<libc::socklen_t>,
+    lengths: Vec<usize>,
+}
+
+impl RecvBatch {
+    pub fn new(batch_size: usize) -> Self {
+        let batch_size = batch_size.max(1);
+
+        Self {
+            batch_size,
+            buffers: RecvBufferPool::new(batch_size),
+            // SAFETY: sockaddr_storage is plain data, so all zeroes is a valid value
+            addresses: vec![unsafe { mem::zeroed() }; batch_size],
+            address_lengths: vec![0; batch_size],
+            lengths: vec![0; batch_size],
+        }
+    }
+
+    /// Waits for datagrams, then receives as many as fit in the batch, returning how many arrived.
+    pub async fn recv_from(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        loop {
+            socket.readable().await?;
+
+            match socket.try_io(Interest::READABLE, || self.try_recv_from(socket)) {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn try_recv_from(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        let mut iovecs: Vec<libc::iovec> = self
+            .buffers
+            .slots(self.batch_size)
+            .chunks_mut(MAX_DATAGRAM_SIZE)
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr().cast(),
+                iov_len: buffer.len(),
+            })
+            .collect();
+
+        let mut headers: Vec<libc::mmsghdr> = iovecs
+            .iter_mut()
+            .zip(self.addresses.iter_mut())
+            .map(|(iovec, address)| {
+                // SAFETY: mmsghdr is plain data, so all zeroes is a valid value
+                let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                header.msg_hdr.msg_name = (address as *mut libc::sockaddr_storage).cast();
+                header.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+                header.msg_hdr.msg_iov = iovec;
+                header.msg_hdr.msg_iovlen = 1;
+                header
+            })
+            .collect();
+
+        // SAFETY: every header points at a buffer and address owned by this batch,
+        // and they all outlive the call
+        let count = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                headers.len() as _,
+                libc::MSG_DONTWAIT,
+                ptr::null_mut(),
+            )
+        };
+
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let count = count as usize;
+        for (index, header) in headers.iter().take(count).enumerate() {
+            // Truncated datagrams can't be valid packets, so they're treated as empty
+            self.lengths[index] = if header.msg_hdr.msg_flags & libc::MSG_TRUNC != 0 {
+                0
+            } else {
+                header.msg_len as usize
+            };
+            self.address_lengths[index] = header.msg_hdr.msg_namelen;
+        }
+
+        Ok(count)
+    }
+
+    /// The datagrams from the last receive, split off the pooled buffers without copying.
+    pub fn messages(&mut self, count: usize) -> impl Iterator<Item = (BytesMut, SocketAddr)> + '_ {
+        (0..count).filter_map(move |index| {
+            let length = self.lengths[index];
+            let message = self.buffers.split_datagram(length);
+
+            // The last datagram's slot is left for the next receive to use
+            if index + 1 < count {
+                self.buffers.skip(MAX_DATAGRAM_SIZE - length);
+            }
+
+            if length == 0 {
+                return None;
+            }
+
+            // SAFETY: the kernel wrote a valid address of this length
+            let address =
+                unsafe { SockAddr::new(self.addresses[index], self.address_lengths[index]) };
+            let peer = address.as_socket()?;
+
+            Some((message, peer))
+        })
+    }
+}
+
+/// Sends every datagram to a peer, using as few `sendmmsg` calls as possible.
+pub async fn send_batch(
+    socket: &UdpSocket,
+    datagrams: &[Vec<u8>],
+    peer: SocketAddr,
+) -> io::Result<()> {
+    let address = SockAddr::from(peer);
+    let mut sent = 0;
+
+    while sent < datagrams.len() {
+        socket.writable().await?;
+
+        match socket.try_io(Interest::WRITABLE, || {
+            try_send_batch(socket, &datagrams[sent..], &address)
+        }) {
+            Ok(count) => sent += count,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
+
+fn try_send_batch(
+    socket: &UdpSocket,
+    datagrams: &[Vec<u8>],
+    address: &SockAddr,
+) -> io::Result<usize> {
+    let mut iovecs: Vec<libc::iovec> = datagrams
+        .iter()
+        .map(|datagram| libc::iovec {
+            iov_base: datagram.as_ptr() as *mut libc::c_void,
+            iov_len: datagram.len(),
+        })
+        .collect();
+
+    let mut headers: Vec<libc::mmsghdr> = iovecs
+        .iter_mut()
+        .map(|iovec| {
+            // SAFETY: mmsghdr is plain data, so all zeroes is a valid value
+            let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+            header.msg_hdr.msg_name = address.as_ptr() as *mut libc::c_void;
+            header.msg_hdr.msg_namelen = address.len();
+            header.msg_hdr.msg_iov = iovec;
+            header.msg_hdr.msg_iovlen = 1;
+            header
+        })
+        .collect();
+
+    // SAFETY: every header points at a datagram and the address, which outlive the call.
+    // The kernel only reads from them when sending.
+    let count = unsafe {
+        libc::sendmmsg(
+            socket.as_raw_fd(),
+            headers.as_mut_ptr(),
+            headers.len() as _,
+            libc::MSG_DONTWAIT,
+        )
+    };
+
+    if count < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(count as usize)
+}
+
+/// Binds several sockets to the same address with SO_REUSEPORT.
+/// The kernel hashes each peer to one of them, so receiving can be spread across cores.
+pub async fn bind_reuse_port_sockets(addr: &str, count: usize) -> io::Result<Vec<UdpSocket>> {
+    let address = lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address to bind to"))?;
+
+    (0..count.max(1))
+        .map(|_| {
+            let socket = Socket::new(
+                Domain::for_address(address),
+                Type::DGRAM,
+                Some(Protocol::UDP),
+            )?;
+            socket.set_reuse_port(true)?;
+            socket.set_nonblocking(true)?;
+            socket.bind(&address.into())?;
+            UdpSocket::from_std(socket.into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_send_and_receive_batches() {
+        let receiver = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("Should have succeeded!");
+        let sender = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("Should have succeeded!");
+        let receiver_addr = receiver.local_addr().expect("Should have succeeded!");
+        let sender_addr = sender.local_addr().expect("Should have succeeded!");
+
+        let datagrams = vec![vec![1, 2, 3], vec![4, 5], vec![6]];
+        send_batch(&sender, &datagrams, receiver_addr)
+            .await
+            .expect("Should have succeeded!");
+
+        let mut batch = RecvBatch::new(8);
+        let mut messages = vec![];
+        while messages.len() < datagrams.len() {
+            let count = batch
+                .recv_from(&receiver)
+                .await
+                .expect("Should have succeeded!");
+            messages.extend(batch.messages(count));
+        }
+
+        let expected: Vec<(BytesMut, SocketAddr)> = datagrams
+            .iter()
+            .map(|datagram| (BytesMut::from(datagram.as_slice()), sender_addr))
+            .collect();
+        assert_eq!(messages, expected);
+    }
+
+    #[tokio::test]
+    async fn should_bind_several_sockets_to_one_port() {
+        let first = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("Should have succeeded!");
+        let addr = first.local_addr().expect("Should have succeeded!");
+        drop(first);
+
+        let sockets = bind_reuse_port_sockets(&addr.to_string(), 3)
+            .await
+            .expect("Should have succeeded!");
+        assert_eq!(sockets.len(), 3);
+        for socket in sockets {
+            assert_eq!(socket.local_addr().expect("Should have succeeded!"), addr);
+        }
+    }
+}
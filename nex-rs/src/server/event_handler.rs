@@ -13,6 +13,15 @@ pub trait EventHandler {
         packet: &PacketV1,
     ) -> ServerResult<()>;
     async fn on_data(&self, client: &mut ClientConnection, packet: &PacketV1) -> ServerResult<()>;
+    /// Receives the decrypted payload of an unreliable data packet.
+    async fn on_unreliable_data(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+        _payload: &[u8],
+    ) -> ServerResult<()> {
+        Ok(())
+    }
     async fn on_disconnect(
         &self,
         client: &mut ClientConnection,
@@ -25,6 +34,9 @@ pub trait EventHandler {
         client: &mut ClientConnection,
         rmc_request: &RMCRequest,
     ) -> ServerResult<()>;
+    /// Called when no route handles a request, before it's answered with Core::NotImplemented.
+    async fn on_unrouted_method(&self, _client: &mut ClientConnection, _rmc_request: &RMCRequest) {}
+    /// Called with names like `TicketGrantingMethod::Login` when a dispatcher routes a request.
     async fn on_protocol_method(&self, method_name: String);
     async fn on_error(&self, error: &NexError);
 }
@@ -0,0 +1,28 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters for the receive pipeline.
+/// These are updated without locks, so they can be read at any time.
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    received_packets: AtomicU64,
+    dropped_packets: AtomicU64,
+}
+
+impl ServerMetrics {
+    pub fn received_packets(&self) -> u64 {
+        self.received_packets.load(Ordering::Relaxed)
+    }
+
+    /// Packets that were shed because their worker's queue was full.
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped_packets.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn record_received_packet(&self) {
+        self.received_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_dropped_packet(&self) {
+        self.dropped_packets.fetch_add(1, Ordering::Relaxed);
+    }
+}
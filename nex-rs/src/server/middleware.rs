@@ -0,0 +1,330 @@
+use crate::{client::ClientConnection, nex_types::ResultCode, rmc::RMCRequest, route::NexProtocol};
+use std::sync::Arc;
+
+/// Runs around the requests it's registered for, such as for logging, auth checks or metrics.
+/// Requests no route handles go through middleware too, before they're answered with Core::NotImplemented.
+#[async_trait::async_trait]
+pub trait Middleware: Send + Sync {
+    /// Called before the method's handler.
+    /// Returning a result code answers the request with it instead of running the handler.
+    async fn before(
+        &self,
+        _client: &mut ClientConnection,
+        _request: &RMCRequest,
+    ) -> Result<(), ResultCode> {
+        Ok(())
+    }
+
+    /// Called with how the request was answered, but only if this middleware's `before` ran.
+    /// That includes a middleware that rejected the request, and not the ones after it.
+    /// An answer that couldn't be sent is reported as Core::Exception.
+    async fn after(
+        &self,
+        _client: &mut ClientConnection,
+        _request: &RMCRequest,
+        _outcome: Result<(), ResultCode>,
+    ) {
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MiddlewareScope {
+    Server,
+    Protocol {
+        protocol_id: u8,
+        custom_id: u16,
+    },
+    Method {
+        protocol_id: u8,
+        custom_id: u16,
+        method_id: u32,
+    },
+}
+
+impl MiddlewareScope {
+    fn matches(&self, request: &RMCRequest) -> bool {
+        match *self {
+            Self::Server => true,
+            Self::Protocol {
+                protocol_id,
+                custom_id,
+            } => request.is_protocol_id(protocol_id, custom_id),
+            Self::Method {
+                protocol_id,
+                custom_id,
+                method_id,
+            } => request.is_protocol_id(protocol_id, custom_id) && request.method_id == method_id,
+        }
+    }
+}
+
+/// How many of a request's matching middleware ran their `before` hook, and whether one rejected it.
+/// [MiddlewareChain::after] only unwinds the middleware that ran.
+#[must_use]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MiddlewareRun {
+    ran: usize,
+    rejection: Option<ResultCode>,
+}
+
+impl MiddlewareRun {
+    /// The result code a middleware rejected the request with, which it should be answered with instead.
+    pub fn rejection(&self) -> Option<ResultCode> {
+        self.rejection
+    }
+}
+
+/// The middleware of a server.
+/// `before` hooks run in the order middleware was added, and `after` hooks in reverse.
+#[derive(Default, Clone)]
+pub struct MiddlewareChain {
+    middlewares: Vec<(MiddlewareScope, Arc<dyn Middleware>)>,
+}
+
+impl MiddlewareChain {
+    /// Adds middleware that runs for every method.
+    pub fn add(&mut self, middleware: impl Middleware + 'static) {
+        self.middlewares
+            .push((MiddlewareScope::Server, Arc::new(middleware)));
+    }
+
+    pub fn add_for_protocol<T: NexProtocol>(&mut self, middleware: impl Middleware + 'static) {
+        let scope = MiddlewareScope::Protocol {
+            protocol_id: T::PROTOCOL_ID,
+            custom_id: T::CUSTOM_ID,
+        };
+        self.middlewares.push((scope, Arc::new(middleware)));
+    }
+
+    pub fn add_for_method<T: NexProtocol + Into<u32>>(
+        &mut self,
+        method: T,
+        middleware: impl Middleware + 'static,
+    ) {
+        let scope = MiddlewareScope::Method {
+            protocol_id: T::PROTOCOL_ID,
+            custom_id: T::CUSTOM_ID,
+            method_id: method.into(),
+        };
+        self.middlewares.push((scope, Arc::new(middleware)));
+    }
+
+    fn matching<'a>(
+        &'a self,
+        request: &'a RMCRequest,
+    ) -> impl DoubleEndedIterator<Item = &'a Arc<dyn Middleware>> {
+        self.middlewares
+            .iter()
+            .filter(|(scope, _)| scope.matches(request))
+            .map(|(_, middleware)| middleware)
+    }
+
+    /// Stops at the first middleware that rejects the request.
+    pub async fn before(
+        &self,
+        client: &mut ClientConnection,
+        request: &RMCRequest,
+    ) -> MiddlewareRun {
+        let mut ran = 0;
+        for middleware in self.matching(request) {
+            ran += 1;
+            if let Err(error_code) = middleware.before(client, request).await {
+                return MiddlewareRun {
+                    ran,
+                    rejection: Some(error_code),
+                };
+            }
+        }
+
+        MiddlewareRun {
+            ran,
+            rejection: None,
+        }
+    }
+
+    /// Unwinds the middleware whose `before` ran, in reverse.
+    pub async fn after(
+        &self,
+        run: MiddlewareRun,
+        client: &mut ClientConnection,
+        request: &RMCRequest,
+        outcome: Result<(), ResultCode>,
+    ) {
+        let ran: Vec<_> = self.matching(request).take(run.ran).collect();
+        for middleware in ran.into_iter().rev() {
+            middleware.after(client, request, outcome).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::ClientContext;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum TestProtocol {
+        First = 1,
+        Second = 2,
+    }
+
+    impl From<TestProtocol> for u32 {
+        fn from(method: TestProtocol) -> Self {
+            method as u32
+        }
+    }
+
+    impl NexProtocol for TestProtocol {
+        const PROTOCOL_ID: u8 = 5;
+    }
+
+    struct RecordingMiddleware {
+        name: &'static str,
+        rejection: Option<ResultCode>,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for RecordingMiddleware {
+        async fn before(
+            &self,
+            _client: &mut ClientConnection,
+            _request: &RMCRequest,
+        ) -> Result<(), ResultCode> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("before {}", self.name));
+            self.rejection.map_or(Ok(()), Err)
+        }
+
+        async fn after(
+            &self,
+            _client: &mut ClientConnection,
+            _request: &RMCRequest,
+            outcome: Result<(), ResultCode>,
+        ) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("after {} {:?}", self.name, outcome.is_ok()));
+        }
+    }
+
+    fn new_client() -> ClientConnection {
+        let addr = "127.0.0.1:12345".parse().unwrap();
+        ClientConnection::new(addr, ClientContext::new(0, ""), 0)
+    }
+
+    fn new_middleware(
+        name: &'static str,
+        rejection: Option<ResultCode>,
+        calls: &Arc<Mutex<Vec<String>>>,
+    ) -> RecordingMiddleware {
+        RecordingMiddleware {
+            name,
+            rejection,
+            calls: calls.clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_run_matching_middleware_in_order() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let mut chain = MiddlewareChain::default();
+        chain.add(new_middleware("server", None, &calls));
+        chain.add_for_protocol::<TestProtocol>(new_middleware("protocol", None, &calls));
+        chain.add_for_method(TestProtocol::Second, new_middleware("method", None, &calls));
+
+        let mut client = new_client();
+        let request = RMCRequest::new(5, TestProtocol::First, 1, vec![]);
+
+        let run = chain.before(&mut client, &request).await;
+        assert_eq!(run.rejection(), None);
+        chain.after(run, &mut client, &request, Ok(())).await;
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "before server",
+                "before protocol",
+                "after protocol true",
+                "after server true"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_match_protocols_like_requests_do() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let mut chain = MiddlewareChain::default();
+        chain.add_for_protocol::<TestProtocol>(new_middleware("protocol", None, &calls));
+        chain.add_for_method(TestProtocol::First, new_middleware("method", None, &calls));
+
+        // Custom ids only matter for the extended protocol
+        let mut client = new_client();
+        let request = RMCRequest::new(5, TestProtocol::First, 1, vec![]).with_custom_id(9);
+        assert!(request.is_method(TestProtocol::First));
+
+        let run = chain.before(&mut client, &request).await;
+        chain.after(run, &mut client, &request, Ok(())).await;
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "before protocol",
+                "before method",
+                "after method true",
+                "after protocol true"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_stop_at_the_first_rejection() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let rejection = ResultCode::from(0x80010006);
+        let mut chain = MiddlewareChain::default();
+        chain.add_for_method(
+            TestProtocol::First,
+            new_middleware("auth", Some(rejection), &calls),
+        );
+        chain.add(new_middleware("server", None, &calls));
+
+        let mut client = new_client();
+        let request = RMCRequest::new(5, TestProtocol::First, 1, vec![]);
+
+        let run = chain.before(&mut client, &request).await;
+        assert_eq!(run.rejection(), Some(rejection));
+        assert_eq!(*calls.lock().unwrap(), vec!["before auth"]);
+    }
+
+    #[tokio::test]
+    async fn should_only_unwind_middleware_that_ran() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let rejection = ResultCode::from(0x80010006);
+        let mut chain = MiddlewareChain::default();
+        chain.add(new_middleware("server", None, &calls));
+        chain.add_for_protocol::<TestProtocol>(new_middleware("auth", Some(rejection), &calls));
+        chain.add(new_middleware("metrics", None, &calls));
+
+        let mut client = new_client();
+        let request = RMCRequest::new(5, TestProtocol::First, 1, vec![]);
+
+        let run = chain.before(&mut client, &request).await;
+        chain
+            .after(run, &mut client, &request, Err(rejection))
+            .await;
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "before server",
+                "before auth",
+                "after auth false",
+                "after server false"
+            ]
+        );
+    }
+}
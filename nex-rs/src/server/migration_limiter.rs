@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How many packets from one unknown address can try to migrate a connection per window.
+const MAX_ATTEMPTS_PER_WINDOW: u32 = 4;
+const WINDOW: Duration = Duration::from_secs(10);
+/// Bounds the memory spoofed addresses can use. New addresses are refused while this many are tracked.
+const MAX_TRACKED_ADDRESSES: usize = 4096;
+
+/// Limits how often packets from an unknown address can try to migrate a connection,
+/// since each attempt can check the signatures of many connections.
+#[derive(Debug, Default)]
+pub struct MigrationLimiter {
+    attempts: Mutex<HashMap<SocketAddr, (Instant, u32)>>,
+}
+
+impl MigrationLimiter {
+    /// Records a migration attempt, returning false if the address has used up its attempts.
+    pub(super) fn allow(&self, address: SocketAddr) -> bool {
+        self.allow_at(address, Instant::now())
+    }
+
+    fn allow_at(&self, address: SocketAddr, now: Instant) -> bool {
+        let mut attempts = self
+            .attempts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if !attempts.contains_key(&address) && attempts.len() >= MAX_TRACKED_ADDRESSES {
+            attempts.retain(|_, (window_start, _)| now.duration_since(*window_start) < WINDOW);
+
+            if attempts.len() >= MAX_TRACKED_ADDRESSES {
+                return false;
+            }
+        }
+
+        let (window_start, count) = attempts.entry(address).or_insert((now, 0));
+
+        if now.duration_since(*window_start) >= WINDOW {
+            *window_start = now;
+            *count = 0;
+        }
+
+        if *count >= MAX_ATTEMPTS_PER_WINDOW {
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn should_limit_attempts_per_address() {
+        let limiter = MigrationLimiter::default();
+        let now = Instant::now();
+
+        for _ in 0..MAX_ATTEMPTS_PER_WINDOW {
+            assert!(limiter.allow_at(address(1), now));
+        }
+        assert!(!limiter.allow_at(address(1), now));
+        assert!(limiter.allow_at(address(2), now));
+        assert!(limiter.allow_at(address(1), now + WINDOW));
+    }
+
+    #[test]
+    fn should_refuse_new_addresses_while_full() {
+        let limiter = MigrationLimiter::default();
+        let now = Instant::now();
+
+        for port in 0..MAX_TRACKED_ADDRESSES as u16 {
+            assert!(limiter.allow_at(address(port), now));
+        }
+        assert!(!limiter.allow_at(address(u16::MAX), now));
+        assert!(limiter.allow_at(address(u16::MAX), now + WINDOW));
+    }
+}
@@ -1,11 +1,33 @@
 mod base;
+#[cfg(target_os = "linux")]
+mod batch_io;
 mod event_handler;
+mod metrics;
+mod middleware;
+mod migration_limiter;
+mod recv_buffer_pool;
 mod result;
+mod send_pump;
 mod server_trait;
 mod settings;
+mod settings_builder;
+#[cfg(test)]
+mod test_server;
+mod worker_pool;
 
 pub use base::*;
+#[cfg(target_os = "linux")]
+pub use batch_io::*;
 pub use event_handler::*;
+pub use metrics::*;
+pub use middleware::*;
+pub use migration_limiter::*;
+pub use recv_buffer_pool::*;
 pub use result::*;
+pub use send_pump::*;
 pub use server_trait::*;
 pub use settings::*;
+pub use settings_builder::*;
+#[cfg(test)]
+pub(crate) use test_server::*;
+pub use worker_pool::*;
@@ -0,0 +1,116 @@
+use super::MAX_DATAGRAM_SIZE;
+use bytes::{Buf, BytesMut};
+use std::{collections::VecDeque, mem};
+
+/// The fewest datagrams a chunk has room for.
+const MIN_CHUNK_DATAGRAMS: usize = 64;
+/// Full chunks kept for reuse. Any others are freed once their datagrams are dropped.
+const MAX_RETIRED_CHUNKS: usize = 8;
+
+/// Receive buffers carved out of large chunks.
+/// Datagrams are split off a chunk without copying, and a chunk is reused
+/// once every datagram split from it has been dropped.
+#[derive(Debug)]
+pub struct RecvBufferPool {
+    chunk_size: usize,
+    chunk: BytesMut,
+    /// Used up chunks, waiting for their datagrams to be dropped.
+    retired: VecDeque<BytesMut>,
+}
+
+impl RecvBufferPool {
+    /// `batch_size` is the most datagrams received at once.
+    pub fn new(batch_size: usize) -> Self {
+        let chunk_size = batch_size.max(MIN_CHUNK_DATAGRAMS) * MAX_DATAGRAM_SIZE;
+
+        Self {
+            chunk_size,
+            chunk: BytesMut::zeroed(chunk_size),
+            retired: VecDeque::new(),
+        }
+    }
+
+    /// Room for `count` datagrams, one after another in slots of [MAX_DATAGRAM_SIZE].
+    /// `count` can't be more than the batch size the pool was created with.
+    pub fn slots(&mut self, count: usize) -> &mut [u8] {
+        let size = count * MAX_DATAGRAM_SIZE;
+
+        if self.chunk.len() < size {
+            self.next_chunk();
+        }
+
+        &mut self.chunk[..size]
+    }
+
+    /// Splits a datagram that was received at the start of the slots off the chunk.
+    pub fn split_datagram(&mut self, length: usize) -> BytesMut {
+        self.chunk.split_to(length)
+    }
+
+    /// Skips the rest of a slot, so the next datagram starts at the next slot.
+    pub fn skip(&mut self, length: usize) {
+        self.chunk.advance(length);
+    }
+
+    fn next_chunk(&mut self) {
+        let chunk_size = self.chunk_size;
+        let reclaimed = self.retired.iter_mut().position(|chunk| {
+            chunk.clear();
+            chunk.try_reclaim(chunk_size)
+        });
+
+        let mut chunk = match reclaimed.and_then(|index| self.retired.remove(index)) {
+            Some(chunk) => chunk,
+            None => BytesMut::with_capacity(chunk_size),
+        };
+        chunk.resize(chunk_size, 0);
+
+        let used = mem::replace(&mut self.chunk, chunk);
+        if self.retired.len() < MAX_RETIRED_CHUNKS {
+            self.retired.push_back(used);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn receive(pool: &mut RecvBufferPool, data: &[u8]) -> BytesMut {
+        pool.slots(1)[..data.len()].copy_from_slice(data);
+        pool.split_datagram(data.len())
+    }
+
+    #[test]
+    fn should_split_datagrams_without_gaps() {
+        let mut pool = RecvBufferPool::new(1);
+        let first = receive(&mut pool, &[1, 2, 3]);
+        let second = receive(&mut pool, &[4, 5]);
+
+        assert_eq!(first, [1, 2, 3].as_slice());
+        assert_eq!(second, [4, 5].as_slice());
+        assert_eq!(first.as_ptr().wrapping_add(3), second.as_ptr());
+    }
+
+    #[test]
+    fn should_reuse_chunks_once_their_datagrams_are_dropped() {
+        let mut pool = RecvBufferPool::new(1);
+        let datagram = vec![0xaa; MAX_DATAGRAM_SIZE];
+
+        let first_chunk: Vec<BytesMut> = (0..MIN_CHUNK_DATAGRAMS)
+            .map(|_| receive(&mut pool, &datagram))
+            .collect();
+        let first_chunk_ptr = first_chunk[0].as_ptr();
+
+        // The first chunk is still in use, so a new one is needed
+        let second_chunk: Vec<BytesMut> = (0..MIN_CHUNK_DATAGRAMS)
+            .map(|_| receive(&mut pool, &datagram))
+            .collect();
+        assert_ne!(second_chunk[0].as_ptr(), first_chunk_ptr);
+
+        drop(first_chunk);
+        let reused = receive(&mut pool, &datagram);
+        assert_eq!(reused.as_ptr(), first_chunk_ptr);
+        assert_eq!(reused, datagram.as_slice());
+    }
+}
@@ -1,6 +1,5 @@
 use crate::{client, crypto, packet};
 use snafu::Snafu;
-use std::net::SocketAddr;
 
 #[derive(Debug, PartialEq, Snafu)]
 pub enum Error {
@@ -13,16 +12,15 @@ pub enum Error {
     #[snafu()]
     DataSendError,
     #[snafu(display(
-        "Tried to send too many fragments to {}: sequence_id 0x{:02x}, fragment_id 0x{:x}",
-        client_addr,
-        sequence_id,
-        fragment_id,
+        "Unreliable payload of 0x{:x} bytes does not fit in fragment size 0x{:x}",
+        size,
+        fragment_size
     ))]
-    TooManyFragments {
-        client_addr: SocketAddr,
-        sequence_id: u16,
-        fragment_id: usize,
-    },
+    UnreliablePayloadTooLarge { size: usize, fragment_size: u16 },
+    #[snafu(display("No client is connected with pid {}", pid))]
+    PidNotConnected { pid: u32 },
+    #[snafu(display("Reliable packet {} was never acknowledged", sequence_id))]
+    PacketNotAcknowledged { sequence_id: u16 },
     #[snafu(display(
         "Packet error: {}",
         error.to_string()
@@ -43,10 +41,75 @@ pub enum Error {
         error.to_string()
     ))]
     IoError { error: no_std_io::Error },
+    #[snafu(display(
+        "Settings error: {}",
+        error.to_string()
+    ))]
+    SettingsError { error: SettingsError },
     #[snafu(display("Error: {}", message))]
     Generic { message: String },
 }
 
+#[derive(Debug, PartialEq, Snafu)]
+pub enum SettingsError {
+    #[snafu(display(
+        "Fragment size {} must be between 1 and {}",
+        fragment_size,
+        max_fragment_size
+    ))]
+    InvalidFragmentSize {
+        fragment_size: u16,
+        max_fragment_size: u16,
+    },
+    #[snafu(display("Flags version {} does not fit in a byte", flags_version))]
+    InvalidFlagsVersion { flags_version: u32 },
+    #[snafu(display(
+        "NEX version {} can't be used with flags version {}",
+        nex_version,
+        flags_version
+    ))]
+    IncompatibleVersions {
+        nex_version: u32,
+        flags_version: u32,
+    },
+    #[snafu(display("Checksum version {} must be 0 or 1", checksum_version))]
+    InvalidChecksumVersion { checksum_version: u32 },
+    #[snafu(display("Ping timeout must be at least 1 second"))]
+    InvalidPingTimeout,
+    #[snafu(display("{} of {} must be between {} and {}", name, value, min, max))]
+    OutOfRange {
+        name: &'static str,
+        value: usize,
+        min: usize,
+        max: usize,
+    },
+    #[snafu(display(
+        "Max queued bytes of {} can't hold a fragment of {} bytes",
+        max_queued_bytes,
+        fragment_size
+    ))]
+    QueueSmallerThanFragment {
+        max_queued_bytes: usize,
+        fragment_size: u16,
+    },
+    #[snafu(display("Access key {} is used by more than one title", access_key))]
+    DuplicateAccessKey { access_key: String },
+    #[snafu(display("Cannot read settings file {}: {}", path, message))]
+    ReadFile { path: String, message: String },
+    #[snafu(display("Cannot parse settings: {}", message))]
+    Parse { message: String },
+    #[snafu(display("Invalid value {} for environment variable {}", value, name))]
+    InvalidEnvVar { name: String, value: String },
+}
+
+pub type SettingsResult<T> = Result<T, SettingsError>;
+
+impl From<SettingsError> for Error {
+    fn from(error: SettingsError) -> Self {
+        Self::SettingsError { error }
+    }
+}
+
 impl From<packet::Error> for Error {
     fn from(error: packet::Error) -> Self {
         Self::PacketError { error }
@@ -0,0 +1,29 @@
+use super::Server;
+use crate::client::ClientConnection;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// Runs client send queues on their own tasks.
+/// Sends only need `&self`, so they hand claimed queues to the pump instead of spawning tasks themselves.
+pub struct SendPump;
+
+impl SendPump {
+    pub fn start<T: Server + Sized + Send + Sync + 'static>(
+        server: &Arc<T>,
+        receiver: UnboundedReceiver<ClientConnection>,
+    ) {
+        tokio::spawn(Self::run(Arc::clone(server), receiver));
+    }
+
+    async fn run<T: Server + Sized + Send + Sync + 'static>(
+        server: Arc<T>,
+        mut receiver: UnboundedReceiver<ClientConnection>,
+    ) {
+        while let Some(mut client) = receiver.recv().await {
+            let server = Arc::clone(&server);
+            tokio::spawn(async move {
+                server.run_send_queue(&mut client).await;
+            });
+        }
+    }
+}
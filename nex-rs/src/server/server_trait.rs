@@ -1,23 +1,46 @@
-use super::{BaseServer, ClientMap, Error, EventHandler, ServerResult};
+use super::{
+    BaseServer, ConnectionMigration, Error, EventHandler, MiddlewareChain, PacketWorkerPool,
+    RecvBufferPool, SendPump, ServerMetrics, ServerResult, SignaturePolicy,
+};
 use crate::{
-    client::ClientConnection,
+    client::{ClientConnection, ClientRegistry, SendQueuePoll},
+    counter::SequenceIdCounter,
+    nex_types::{Core, ResultCode},
     packet::{Packet, PacketFlag, PacketType, PacketV1},
+    rmc::RMCRequest,
+    route::NexProtocol,
 };
 use async_trait::async_trait;
-use no_std_io::{StreamContainer, StreamWriter};
+use bytes::BytesMut;
+use no_std_io::{StreamContainer, StreamReader, StreamWriter};
 use rand::RngCore;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::{net::UdpSocket, sync::RwLock, time};
+use tokio::{net::UdpSocket, sync::mpsc, time};
+
+#[cfg(target_os = "linux")]
+use super::{bind_reuse_port_sockets, send_batch, RecvBatch};
 
 #[async_trait]
 pub trait Server: EventHandler {
     fn get_base(&self) -> &BaseServer;
     fn get_mut_base(&mut self) -> &mut BaseServer;
 
-    fn get_clients(&self) -> Arc<RwLock<ClientMap>> {
+    fn get_clients(&self) -> Arc<ClientRegistry> {
         Arc::clone(&self.get_base().clients)
     }
 
+    /// Finds the connected client that's logged in as `pid`.
+    fn find_client_by_pid(&self, pid: u32) -> Option<ClientConnection> {
+        self.get_base()
+            .clients
+            .get_by_pid(pid)
+            .filter(|client| client.is_connected())
+    }
+
+    fn is_pid_online(&self, pid: u32) -> bool {
+        self.find_client_by_pid(pid).is_some()
+    }
+
     fn get_access_key(&self) -> String {
         self.get_base().settings.access_key.to_string()
     }
@@ -49,18 +72,50 @@ pub trait Server: EventHandler {
         self.get_mut_base().settings.flags_version = flags_version;
     }
 
+    fn get_metrics(&self) -> &ServerMetrics {
+        &self.get_base().metrics
+    }
+
+    fn get_middleware(&self) -> &MiddlewareChain {
+        &self.get_base().middleware
+    }
+
+    /// Middleware should be added before the server starts listening.
+    fn get_mut_middleware(&mut self) -> &mut MiddlewareChain {
+        &mut self.get_mut_base().middleware
+    }
+
     fn get_socket(&self) -> ServerResult<&UdpSocket> {
         self.get_base().socket.as_ref().ok_or(Error::NoSocket)
     }
 
     async fn initialize(&mut self, addr: &str) -> ServerResult<()> {
-        let socket = UdpSocket::bind(addr)
-            .await
-            .map_err(|_| Error::CouldNoBindToAddress)?;
+        // Setters don't validate, so settings are checked once before serving
+        self.get_base().settings.validate()?;
+
+        #[cfg(target_os = "linux")]
+        {
+            let socket_count = self.get_base().settings.socket_count;
+
+            if socket_count > 1 {
+                let mut sockets = bind_reuse_port_sockets(addr, socket_count)
+                    .await
+                    .map_err(|_| Error::CouldNoBindToAddress)?;
+                let socket = sockets.remove(0);
+                self.get_mut_base().socket = Some(socket);
+                self.get_mut_base().reuse_port_sockets = sockets;
+            }
+        }
+
+        if self.get_base().socket.is_none() {
+            let socket = UdpSocket::bind(addr)
+                .await
+                .map_err(|_| Error::CouldNoBindToAddress)?;
 
-        self.get_mut_base().socket = Some(socket);
+            self.get_mut_base().socket = Some(socket);
+        }
 
-        let clients_lock = Arc::clone(&self.get_base().clients);
+        let clients = self.get_clients();
         let ping_kick_thread = tokio::spawn(async move {
             let mut invertal = time::interval(Duration::from_secs(3));
             invertal.tick().await;
@@ -68,28 +123,15 @@ pub trait Server: EventHandler {
             loop {
                 invertal.tick().await;
 
-                // We can't use iterator-like methods since each client has a lock.
-                // We could have a new list and re-add each item, but we'll add almost
-                // every client each time.
-                // A kick list means iterating over a map, then a list, but we'll almost never
-                // iterate over the kick list.
-                let mut kick_list = vec![];
-                let clients = clients_lock.read().await;
-
-                for (addr, client_lock) in clients.iter() {
-                    let mut client = client_lock.write().await;
+                // Working from a snapshot means the registry is never locked
+                // for more than one shard at a time
+                for mut client in clients.clients() {
                     if client.get_kick_timer() == 0 || !client.is_connected() {
-                        kick_list.push(*addr);
+                        clients.remove_client(&client);
                     } else {
                         client.decrement_kick_timer(3);
                     }
                 }
-
-                drop(clients);
-                let mut clients = clients_lock.write().await;
-                for kick_addr in kick_list.iter() {
-                    clients.remove(kick_addr);
-                }
             }
         });
 
@@ -103,37 +145,100 @@ pub trait Server: EventHandler {
         addr: &str,
     ) -> ServerResult<()> {
         server.initialize(addr).await?;
-        let server = Arc::new(server);
 
-        loop {
-            let (buf, peer) = server.receive_data().await?;
-            let clone = Arc::clone(&server);
+        let (send_pump, send_pump_receiver) = mpsc::unbounded_channel();
+        server.get_mut_base().send_pump = Some(send_pump);
+        let server = Arc::new(server);
+        SendPump::start(&server, send_pump_receiver);
+
+        let settings = &server.get_base().settings;
+        let workers = Arc::new(PacketWorkerPool::new(
+            &server,
+            settings.worker_count,
+            settings.worker_queue_size,
+        ));
+
+        for socket_index in 0..server.get_base().reuse_port_sockets.len() {
+            let server = Arc::clone(&server);
+            let workers = Arc::clone(&workers);
             tokio::spawn(async move {
-                if let Err(error) = clone.handle_socket_message(buf, peer).await {
-                    clone.on_error(&error.into()).await;
+                let socket = &server.get_base().reuse_port_sockets[socket_index];
+                if let Err(error) = server.receive_messages(socket, &workers).await {
+                    server.on_error(&error.into()).await;
                 }
             });
         }
+
+        server
+            .receive_messages(server.get_socket()?, &workers)
+            .await
+    }
+
+    /// Receives messages from a socket and queues them for the packet workers.
+    async fn receive_messages(
+        &self,
+        socket: &UdpSocket,
+        workers: &PacketWorkerPool,
+    ) -> ServerResult<()> {
+        let metrics = self.get_metrics();
+
+        #[cfg(target_os = "linux")]
+        if self.get_base().settings.batched_io {
+            let mut batch = RecvBatch::new(self.get_base().settings.io_batch_size);
+
+            loop {
+                let count = batch
+                    .recv_from(socket)
+                    .await
+                    .map_err(|_| Error::DataReceiveError)?;
+
+                for (message, peer) in batch.messages(count) {
+                    metrics.record_received_packet();
+
+                    if !workers.queue_message(message, peer) {
+                        metrics.record_dropped_packet();
+                    }
+                }
+            }
+        }
+
+        let mut buffers = RecvBufferPool::new(1);
+
+        loop {
+            let (message, peer) = self.receive_data_from(socket, &mut buffers).await?;
+            metrics.record_received_packet();
+
+            if !workers.queue_message(message, peer) {
+                metrics.record_dropped_packet();
+            }
+        }
     }
 
-    async fn receive_data(&self) -> ServerResult<(Vec<u8>, SocketAddr)> {
-        let mut buf: Vec<u8> = vec![0; 0x1000];
-        let socket = &self.get_base().socket.as_ref().ok_or(Error::NoSocket)?;
+    async fn receive_data(
+        &self,
+        buffers: &mut RecvBufferPool,
+    ) -> ServerResult<(BytesMut, SocketAddr)> {
+        self.receive_data_from(self.get_socket()?, buffers).await
+    }
 
+    /// Receives one datagram into the pooled buffers.
+    async fn receive_data_from(
+        &self,
+        socket: &UdpSocket,
+        buffers: &mut RecvBufferPool,
+    ) -> ServerResult<(BytesMut, SocketAddr)> {
         let (receive_size, peer) = socket
-            .recv_from(&mut buf)
+            .recv_from(buffers.slots(1))
             .await
             .map_err(|_| Error::DataReceiveError)?;
 
-        buf.resize(receive_size, 0);
-
-        Ok((buf, peer))
+        Ok((buffers.split_datagram(receive_size), peer))
     }
 
     async fn emit_packet_events(
         &self,
         client: &mut ClientConnection,
-        packet: &PacketV1,
+        packet: &mut PacketV1,
     ) -> ServerResult<()> {
         match packet.get_packet_type() {
             PacketType::Syn => {
@@ -148,9 +253,14 @@ pub trait Server: EventHandler {
             PacketType::Data => {
                 self.on_data(client, packet).await?;
 
-                if client.can_decode_rmc_request(packet) {
+                if packet.is_unreliable_data() {
+                    client.decrypt_unreliable_data(packet)?;
+                    let packet = &*packet;
+                    self.on_unreliable_data(client, packet, packet.get_payload())
+                        .await?;
+                } else if client.can_decode_rmc_request(packet) {
                     let rmc_request = client.decode_rmc_request(packet)?;
-                    self.on_rmc_request(client, &rmc_request).await?;
+                    client.queue_rmc_request(rmc_request);
                 }
             }
             PacketType::Ping => {
@@ -170,8 +280,10 @@ pub trait Server: EventHandler {
             return true;
         }
 
-        // Ignore packets we're not expecting
+        // Ignore packets we're not expecting.
+        // Unreliable data can arrive in any order, so it isn't sequence checked.
         if packet_type != PacketType::Ping
+            && !packet.is_unreliable_data()
             && packet.get_sequence_id() != client.get_sequence_id_in()
         {
             return true;
@@ -180,6 +292,17 @@ pub trait Server: EventHandler {
         false
     }
 
+    /// Whether this is a packet we've already handled, accounting for sequence id wraparound.
+    fn is_resent_packet(&self, client: &ClientConnection, packet: &PacketV1) -> bool {
+        let packet_type = packet.get_packet_type();
+
+        client.is_connected()
+            && packet_type != PacketType::Syn
+            && packet_type != PacketType::Ping
+            && !packet.is_unreliable_data()
+            && SequenceIdCounter::is_before(packet.get_sequence_id(), client.get_sequence_id_in())
+    }
+
     fn handle_connection_init(&self, client: &mut ClientConnection, packet: &PacketV1) {
         match packet.get_packet_type() {
             PacketType::Syn => {
@@ -196,8 +319,8 @@ pub trait Server: EventHandler {
     }
 
     fn increment_sequence_id_in(&self, client: &mut ClientConnection, packet: &PacketV1) {
-        // Pings have their own sequence ids
-        if packet.get_packet_type() != PacketType::Ping {
+        // Pings and unreliable data have their own sequence ids
+        if packet.get_packet_type() != PacketType::Ping && !packet.is_unreliable_data() {
             client.increment_sequence_id_in();
         }
     }
@@ -208,27 +331,103 @@ pub trait Server: EventHandler {
         }
     }
 
-    async fn handle_socket_message(&self, message: Vec<u8>, peer: SocketAddr) -> ServerResult<()> {
+    /// Handles a message from the socket, returning the client it was for.
+    async fn handle_socket_message(
+        &self,
+        message: BytesMut,
+        peer: SocketAddr,
+    ) -> ServerResult<Option<ClientConnection>> {
         let settings = &self.get_base().settings;
-        let packet = PacketV1::read_packet(message, self.get_flags_version())?;
-        let clients_lock = self.get_clients();
+        let clients = &self.get_base().clients;
 
-        if packet.get_packet_type() == PacketType::Syn {
-            let mut clients = clients_lock.write().await;
-            clients.insert(
+        // A SYN is read with its title's flags version, which isn't known until the title is found
+        let packet = if PacketV1::is_syn_packet(&message) {
+            let (title, packet) = settings.find_title(&message)?;
+            clients.insert(ClientConnection::new(
                 peer,
-                RwLock::new(ClientConnection::new(
-                    peer,
-                    settings.create_client_context(),
-                    settings.ping_timeout,
-                )),
-            );
+                settings.create_client_context(&title),
+                settings.ping_timeout,
+            ));
+            packet
+        } else {
+            let flags_version = clients
+                .get(&peer)
+                .map_or(self.get_flags_version(), |client| client.flags_version());
+            PacketV1::read_packet(message, flags_version)?
+        };
+
+        let client = match clients.get(&peer) {
+            Some(client) => Some(client),
+            None => self.migrate_client(&packet, peer),
+        };
+
+        if let Some(mut client) = client {
+            self.handle_packet(packet, &mut client).await?;
+            return Ok(Some(client));
+        }
+
+        Ok(None)
+    }
+
+    /// Moves an existing connection to `peer` if the packet has a valid signature for it
+    /// and is one the connection hasn't handled yet, so captured packets can't be replayed to take it over.
+    /// Each address only gets a few attempts, since an attempt can check every connection's signature.
+    fn migrate_client(&self, packet: &PacketV1, peer: SocketAddr) -> Option<ClientConnection> {
+        let base = self.get_base();
+
+        // Pings and unreliable data don't advance the sequence id, so they could always be replayed
+        if packet.get_packet_type() == PacketType::Syn
+            || packet.get_packet_type() == PacketType::Ping
+            || packet.is_unreliable_data()
+        {
+            return None;
         }
 
-        let clients = clients_lock.read().await;
+        if base.settings.connection_migration == ConnectionMigration::Disabled
+            || !base.migration_limiter.allow(peer)
+        {
+            return None;
+        }
+
+        let candidates = match base.settings.connection_migration {
+            ConnectionMigration::Disabled => return None,
+            ConnectionMigration::SameIp => base.clients.clients_with_ip(peer.ip()),
+            ConnectionMigration::AnyAddress => base.clients.clients(),
+        };
 
-        if let Some(client) = clients.get(&peer) {
-            return self.handle_packet(packet, client).await;
+        let client = candidates.into_iter().find(|client| {
+            client.is_connected()
+                && !client.get_server_connection_signature().is_empty()
+                && !SequenceIdCounter::is_before(
+                    packet.get_sequence_id(),
+                    client.get_sequence_id_in(),
+                )
+                && client.validate_packet(packet).is_ok()
+        })?;
+
+        if base.clients.migrate(&client, peer) {
+            Some(client)
+        } else {
+            None
+        }
+    }
+
+    /// Validates a packet's header, then its signature according to the signature policy.
+    async fn validate_packet(
+        &self,
+        client: &ClientConnection,
+        packet: &PacketV1,
+    ) -> ServerResult<()> {
+        packet.validate_header()?;
+
+        match self.get_base().settings.signature_policy {
+            SignaturePolicy::Strict => client.validate_packet_signature(packet)?,
+            SignaturePolicy::LogOnly => {
+                if let Err(error) = client.validate_packet_signature(packet) {
+                    self.on_error(&Error::from(error).into()).await;
+                }
+            }
+            SignaturePolicy::Disabled => {}
         }
 
         Ok(())
@@ -236,33 +435,58 @@ pub trait Server: EventHandler {
 
     async fn handle_packet(
         &self,
-        packet: PacketV1,
-        client_lock: &RwLock<ClientConnection>,
+        mut packet: PacketV1,
+        client: &mut ClientConnection,
     ) -> ServerResult<()> {
         let base = self.get_base();
-        let mut client = client_lock.write().await;
+        let _packet_guard = client.lock_packet_handling().await;
+
+        // Disconnected clients can only start over, so their acks and resends aren't handled either
+        if !client.is_connected() && packet.get_packet_type() != PacketType::Syn {
+            return Ok(());
+        }
+
         client.set_kick_timer(base.settings.ping_timeout);
-        client.validate_packet(&packet)?;
+        self.validate_packet(client, &packet).await?;
+
+        if self.accept_acknowledge_packet(client, &packet)? {
+            return Ok(());
+        }
 
-        if self.should_ignore_packet(&mut client, &packet) {
+        if self.is_resent_packet(client, &packet) {
+            // The client missed our ack, so acknowledge it again without handling it twice
+            self.acknowledge_packet(client, &packet).await?;
             return Ok(());
         }
 
-        if self.accept_acknowledge_packet(&packet) {
+        if self.should_ignore_packet(client, &packet) {
             return Ok(());
         }
 
-        self.handle_connection_init(&mut client, &packet);
-        self.acknowledge_packet(&mut client, &packet).await?;
-        self.emit_packet_events(&mut client, &packet).await?;
-        self.increment_sequence_id_in(&mut client, &packet);
-        self.handle_disconnect(&mut client, &packet).await;
+        self.handle_connection_init(client, &packet);
+        self.acknowledge_packet(client, &packet).await?;
+        self.emit_packet_events(client, &mut packet).await?;
+        self.increment_sequence_id_in(client, &packet);
+        self.handle_disconnect(client, &packet).await;
 
         Ok(())
     }
 
+    /// Runs the queued RMC requests of a client that was claimed with [ClientConnection::start_rmc_dispatch], in order.
+    /// The packet workers claim clients and call this on its own task, and no connection locks are held
+    /// while a handler runs.
+    async fn run_rmc_requests(&self, client: &mut ClientConnection) {
+        while let Some(rmc_request) = client.next_rmc_request() {
+            if let Err(error) = self.on_rmc_request(client, &rmc_request).await {
+                self.on_error(&error.into()).await;
+            }
+        }
+    }
+
     async fn kick(&self, client: &mut ClientConnection) {
         client.set_is_connected(false);
+        client.clear_send_queue();
+        self.get_base().clients.remove_client(client);
     }
 
     async fn send_ping(&self, client: &mut ClientConnection) -> ServerResult<()> {
@@ -270,14 +494,43 @@ pub trait Server: EventHandler {
             .await
     }
 
-    fn accept_acknowledge_packet(&self, packet: &PacketV1) -> bool {
+    /// Records the sequence ids an ack packet acknowledges, returning false if it isn't an ack.
+    fn accept_acknowledge_packet(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> ServerResult<bool> {
         let flags = packet.get_flags();
-        if flags.ack() || flags.multi_ack() {
-            // TODO: actually handle ack packets
-            return true;
+
+        if flags.multi_ack() {
+            let mut payload_stream = StreamContainer::new(packet.get_payload());
+
+            // New version
+            if client.nex_version() >= 2 {
+                let _substream_id: u8 = payload_stream.read_stream_le()?;
+                let additional_id_count: u8 = payload_stream.read_stream_le()?;
+                let sequence_id: u16 = payload_stream.read_stream_le()?;
+                let additional_ids = (0..additional_id_count)
+                    .map(|_| payload_stream.read_stream_le())
+                    .collect::<Result<Vec<u16>, _>>()?;
+                client.acknowledge_sequence_ids(sequence_id, &additional_ids);
+            } else {
+                let mut additional_ids = vec![];
+                while payload_stream.get_index() < packet.get_payload().len() {
+                    additional_ids.push(payload_stream.read_stream_le()?);
+                }
+                client.acknowledge_sequence_ids(packet.get_sequence_id(), &additional_ids);
+            }
+
+            return Ok(true);
         }
 
-        false
+        if flags.ack() {
+            client.acknowledge_sequence_id(packet.get_sequence_id());
+            return Ok(true);
+        }
+
+        Ok(false)
     }
 
     async fn acknowledge_packet(
@@ -309,14 +562,13 @@ pub trait Server: EventHandler {
 
         if let Some(payload) = payload {
             if !payload.is_empty() {
-                ack_packet.set_payload(payload);
+                ack_packet.set_payload(payload.as_slice());
             }
         }
 
         match ack_packet.get_packet_type() {
             PacketType::Syn => {
-                ack_packet
-                    .set_connection_signature(client.get_server_connection_signature().to_vec());
+                ack_packet.set_connection_signature(client.get_server_connection_signature());
                 ack_packet.set_supported_functions(packet.flags_version());
                 ack_packet.set_maximum_substream_id(0);
             }
@@ -333,22 +585,22 @@ pub trait Server: EventHandler {
                 let mut payload_stream = StreamContainer::new(vec![]);
 
                 // New version
-                if self.get_base().settings.nex_version >= 2 {
+                if client.nex_version() >= 2 {
                     ack_packet.set_sequence_id(0);
                     ack_packet.set_substream_id(1);
 
                     // We're going to mimic nex-go and do one ack packet
-                    payload_stream.checked_write_stream_le(&0u8); // substream id
-                    payload_stream.checked_write_stream_le(&0u8); // length of additional sequence ids
-                    payload_stream.checked_write_stream_le(&packet.get_sequence_id());
+                    payload_stream.write_stream_le(&0u8)?; // substream id
+                    payload_stream.write_stream_le(&0u8)?; // length of additional sequence ids
+                    payload_stream.write_stream_le(&packet.get_sequence_id())?;
                 }
 
-                ack_packet.set_payload(payload_stream.into_raw())
+                ack_packet.set_payload(payload_stream.into_raw().as_slice())
             }
             _ => {}
         };
 
-        let encoded_packet = &client.encode_packet(&mut ack_packet);
+        let encoded_packet = &client.encode_packet(&mut ack_packet)?;
         self.send_raw(client, encoded_packet).await?;
 
         Ok(())
@@ -358,11 +610,12 @@ pub trait Server: EventHandler {
         &self,
         client: &mut ClientConnection,
         protocol_id: u8,
+        custom_id: u16,
         method_id: MethodId,
         call_id: u32,
         data: Data,
     ) -> ServerResult<()> {
-        let packet = client.new_rmc_success(protocol_id, method_id, call_id, data);
+        let packet = client.new_rmc_success(protocol_id, custom_id, method_id, call_id, data)?;
         self.send(client, packet).await
     }
 
@@ -370,55 +623,186 @@ pub trait Server: EventHandler {
         &self,
         client: &mut ClientConnection,
         protocol_id: u8,
+        custom_id: u16,
         method_id: MethodId,
         call_id: u32,
         error_code: u32,
     ) -> ServerResult<()> {
-        let packet = client.new_rmc_error(protocol_id, method_id, call_id, error_code);
+        let packet =
+            client.new_rmc_error(protocol_id, custom_id, method_id, call_id, error_code)?;
         self.send(client, packet).await
     }
 
+    /// Answers a request that no route handles, so the client doesn't wait for a response until it times out.
+    async fn send_not_implemented(
+        &self,
+        client: &mut ClientConnection,
+        rmc_request: &RMCRequest,
+    ) -> ServerResult<()> {
+        self.on_unrouted_method(client, rmc_request).await;
+        self.send_error(
+            client,
+            rmc_request.protocol_id,
+            rmc_request.custom_id,
+            rmc_request.method_id,
+            rmc_request.call_id,
+            ResultCode::from(Core::NotImplemented).into(),
+        )
+        .await
+    }
+
+    async fn send_data_to_pid(&self, pid: u32, payload: Vec<u8>) -> ServerResult<()> {
+        let mut client = self
+            .find_client_by_pid(pid)
+            .ok_or(Error::PidNotConnected { pid })?;
+        let packet = client.new_data_packet(payload);
+        self.send(&mut client, packet).await
+    }
+
+    /// Sends data to every online pid, returning the pids it was sent to.
+    /// Send errors are reported through [EventHandler::on_error].
+    async fn send_data_to_pids(&self, pids: &[u32], payload: Vec<u8>) -> Vec<u32> {
+        let mut sent_pids = vec![];
+
+        for pid in pids {
+            match self.send_data_to_pid(*pid, payload.clone()).await {
+                Ok(()) => sent_pids.push(*pid),
+                Err(Error::PidNotConnected { .. }) => {}
+                Err(error) => self.on_error(&error.into()).await,
+            }
+        }
+
+        sent_pids
+    }
+
+    async fn send_rmc_request_to_pid<Method: NexProtocol + Into<u32> + Send>(
+        &self,
+        pid: u32,
+        method: Method,
+        parameters: Vec<u8>,
+    ) -> ServerResult<()> {
+        let mut client = self
+            .find_client_by_pid(pid)
+            .ok_or(Error::PidNotConnected { pid })?;
+        let packet = client.new_rmc_request(method, parameters)?;
+        self.send(&mut client, packet).await
+    }
+
+    /// Sends an RMC request to every online pid, returning the pids it was sent to.
+    /// Send errors are reported through [EventHandler::on_error].
+    async fn send_rmc_request_to_pids<Method: NexProtocol + Into<u32> + Copy + Send + Sync>(
+        &self,
+        pids: &[u32],
+        method: Method,
+        parameters: Vec<u8>,
+    ) -> Vec<u32> {
+        let mut sent_pids = vec![];
+
+        for pid in pids {
+            match self
+                .send_rmc_request_to_pid(*pid, method, parameters.clone())
+                .await
+            {
+                Ok(()) => sent_pids.push(*pid),
+                Err(Error::PidNotConnected { .. }) => {}
+                Err(error) => self.on_error(&error.into()).await,
+            }
+        }
+
+        sent_pids
+    }
+
+    /// Queues a packet, fragmenting its payload by the client's fragment size.
+    /// Queued packets are paced out as the client's congestion window allows.
     async fn send(&self, client: &mut ClientConnection, mut packet: PacketV1) -> ServerResult<()> {
-        let fragment_size: usize = self.get_base().settings.fragment_size.into();
-        let data = packet.get_payload().to_vec();
-        let fragment_count = data.len() / fragment_size;
-        let mut fragment_data = data.as_slice();
-        let packet = &mut packet;
-
-        for i in 0..=fragment_count {
-            let fragment_id: u8 = (i + 1).try_into().map_err(|_| Error::TooManyFragments {
-                client_addr: client.get_address(),
-                fragment_id: i + 1,
-                sequence_id: packet.get_sequence_id(),
-            })?;
-
-            if fragment_data.len() < fragment_size {
-                packet.set_payload(fragment_data.to_vec());
-                // Last fragment is always 0
-                self.send_fragment(client, packet, 0).await?;
-            } else {
-                packet.set_payload(data[..fragment_size].to_vec());
-                self.send_fragment(client, packet, fragment_id).await?;
-                fragment_data = &data[fragment_size..];
+        let settings = &self.get_base().settings;
+        let fragment_size = client.get_fragment_size();
+        client.queue_packet(&mut packet, fragment_size.into(), settings.max_queued_bytes)?;
+
+        if client.start_send_queue() {
+            match &self.get_base().send_pump {
+                Some(send_pump) if send_pump.send(client.clone()).is_ok() => {}
+                // Without a running server there's nothing to hand the queue to
+                _ => self.run_send_queue(client).await,
             }
         }
 
         Ok(())
     }
 
-    async fn send_fragment(
+    /// Sends a client's queued packets as its congestion window and pacing allow,
+    /// resending any that aren't acknowledged in time.
+    /// A client is kicked once a packet runs out of resends, since its reliable stream can't continue.
+    /// The queue must be claimed with [ClientConnection::start_send_queue] first.
+    async fn run_send_queue(&self, client: &mut ClientConnection) {
+        let batch_size = self.get_base().settings.io_batch_size.max(1);
+        let mut datagrams = Vec::with_capacity(batch_size);
+
+        loop {
+            match client.poll_send_queue() {
+                SendQueuePoll::Send(data) => {
+                    datagrams.push(data);
+
+                    if datagrams.len() >= batch_size {
+                        self.flush_datagrams(client, &mut datagrams).await;
+                    }
+                }
+                SendQueuePoll::Wait(delay) => {
+                    self.flush_datagrams(client, &mut datagrams).await;
+
+                    tokio::select! {
+                        _ = time::sleep(delay) => {}
+                        _ = client.send_queue_changed() => {}
+                    }
+                }
+                SendQueuePoll::Idle => {
+                    self.flush_datagrams(client, &mut datagrams).await;
+                    break;
+                }
+                SendQueuePoll::Failed(sequence_id) => {
+                    self.flush_datagrams(client, &mut datagrams).await;
+                    self.on_error(&Error::PacketNotAcknowledged { sequence_id }.into())
+                        .await;
+                    // Kicking clears the queue, so the next poll releases the claim
+                    self.kick(client).await;
+                }
+            }
+        }
+    }
+
+    async fn flush_datagrams(&self, client: &ClientConnection, datagrams: &mut Vec<Vec<u8>>) {
+        if datagrams.is_empty() {
+            return;
+        }
+
+        if let Err(error) = self.send_raw_batch(client, datagrams).await {
+            self.on_error(&error.into()).await;
+        }
+
+        datagrams.clear();
+    }
+
+    /// Sends a payload as a single unreliable data packet.
+    /// It isn't acknowledged or resent, so it has to fit in the client's fragment size.
+    async fn send_unreliable(
         &self,
         client: &mut ClientConnection,
-        packet: &mut PacketV1,
-        fragment_id: u8,
-    ) -> ServerResult<usize> {
-        let sequence_id = client.increment_sequence_id_out();
+        payload: Vec<u8>,
+    ) -> ServerResult<()> {
+        let fragment_size = client.get_fragment_size();
 
-        packet.set_sequence_id(sequence_id);
-        packet.set_fragment_id(fragment_id);
+        if payload.len() > fragment_size.into() {
+            return Err(Error::UnreliablePayloadTooLarge {
+                size: payload.len(),
+                fragment_size,
+            });
+        }
+
+        let mut packet = client.new_unreliable_data_packet(payload);
+        let encoded_packet = client.encode_unreliable_packet(&mut packet)?;
+        self.send_raw(client, &encoded_packet).await?;
 
-        let encoded_packet = client.encode_packet(packet);
-        self.send_raw(client, &encoded_packet).await
+        Ok(())
     }
 
     async fn send_raw(&self, client: &ClientConnection, data: &[u8]) -> ServerResult<usize> {
@@ -428,4 +812,45 @@ pub trait Server: EventHandler {
             .await
             .map_err(|_| Error::DataSendError)
     }
+
+    /// Sends several datagrams to a client, in one `sendmmsg` call where possible.
+    async fn send_raw_batch(
+        &self,
+        client: &ClientConnection,
+        datagrams: &[Vec<u8>],
+    ) -> ServerResult<()> {
+        #[cfg(target_os = "linux")]
+        if self.get_base().settings.batched_io {
+            return send_batch(self.get_socket()?, datagrams, client.get_address())
+                .await
+                .map_err(|_| Error::DataSendError);
+        }
+
+        for data in datagrams {
+            self.send_raw(client, data).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::{ServerSettings, SettingsError, TestServer};
+
+    #[tokio::test]
+    async fn should_reject_invalid_settings_on_initialize() {
+        let mut settings = ServerSettings::default();
+        settings.set_ping_timeout(0);
+        let mut server = TestServer::new(settings);
+
+        assert_eq!(
+            server.initialize("127.0.0.1:0").await,
+            Err(Error::SettingsError {
+                error: SettingsError::InvalidPingTimeout
+            })
+        );
+        assert!(server.get_socket().is_err());
+    }
 }
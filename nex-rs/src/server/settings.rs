@@ -1,5 +1,113 @@
-use crate::client::ClientContext;
+use super::{ServerSettingsBuilder, SettingsError, SettingsResult, MAX_DATAGRAM_SIZE};
+use crate::{
+    client::ClientContext,
+    packet::{Packet, PacketResult, PacketType, PacketV1, SignatureContext},
+};
 use getset::{CopyGetters, Getters, Setters};
+use serde::{de::IntoDeserializer, Deserialize};
+use std::{collections::HashSet, num::NonZeroUsize, str::FromStr, thread};
+
+/// The largest fragment size, leaving room for the header, signature and options in a datagram.
+pub const MAX_FRAGMENT_SIZE: u16 = (MAX_DATAGRAM_SIZE - 0x40) as u16;
+
+// Only checksum versions 0 and 1 exist
+const MAX_CHECKSUM_VERSION: u32 = 1;
+
+// recvmmsg and sendmmsg won't take more messages than this in one call
+const MAX_IO_BATCH_SIZE: usize = 1024;
+
+/// How packets from unknown addresses are matched to existing connections,
+/// such as when a client's NAT mapping changes mid-session.
+/// A packet only moves a connection if it carries a valid signature for it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionMigration {
+    /// Packets from unknown addresses are ignored.
+    Disabled,
+    /// Only connections from the same ip can move, which covers NAT port rebinding.
+    SameIp,
+    /// Any connection can move. Every connection's signature may need to be checked.
+    AnyAddress,
+}
+
+/// How packets with invalid signatures are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePolicy {
+    /// Packets with invalid signatures are rejected.
+    Strict,
+    /// Invalid signatures are reported through [EventHandler::on_error](super::EventHandler::on_error),
+    /// but the packets are still handled.
+    LogOnly,
+    /// Signatures aren't checked. Useful when a title's access key isn't known yet.
+    Disabled,
+}
+
+// Environment variables use the same names as settings files
+impl FromStr for ConnectionMigration {
+    type Err = serde::de::value::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(value.into_deserializer())
+    }
+}
+
+impl FromStr for SignaturePolicy {
+    type Err = serde::de::value::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(value.into_deserializer())
+    }
+}
+
+/// The settings for one title served by the server.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TitleSettings {
+    pub access_key: String,
+    pub nex_version: u32,
+    pub flags_version: u32,
+    pub fragment_size: u16,
+}
+
+impl Default for TitleSettings {
+    fn default() -> Self {
+        Self {
+            access_key: "".to_string(),
+            nex_version: 0,
+            flags_version: 1,
+            fragment_size: 1300,
+        }
+    }
+}
+
+impl TitleSettings {
+    pub fn validate(&self) -> SettingsResult<()> {
+        if self.fragment_size == 0 || self.fragment_size > MAX_FRAGMENT_SIZE {
+            return Err(SettingsError::InvalidFragmentSize {
+                fragment_size: self.fragment_size,
+                max_fragment_size: MAX_FRAGMENT_SIZE,
+            });
+        }
+
+        // Only the low byte is sent in the supported functions option
+        if self.flags_version > 0xff {
+            return Err(SettingsError::InvalidFlagsVersion {
+                flags_version: self.flags_version,
+            });
+        }
+
+        // NEX 2 and later acks use substreams, which the legacy flags layout doesn't have
+        if self.nex_version >= 2 && self.flags_version == 0 {
+            return Err(SettingsError::IncompatibleVersions {
+                nex_version: self.nex_version,
+                flags_version: self.flags_version,
+            });
+        }
+
+        Ok(())
+    }
+}
 
 #[derive(Debug, Getters, CopyGetters, Setters)]
 #[getset(skip)]
@@ -10,15 +118,138 @@ pub struct ServerSettings {
     pub(super) nex_version: u32,
     #[getset(set = "pub")]
     pub(super) fragment_size: u16,
+    #[getset(set = "pub")]
     pub(super) flags_version: u32,
     #[getset(set = "pub")]
     pub(super) ping_timeout: u32,
+    #[getset(set = "pub")]
     pub(super) checksum_version: u32,
+    #[getset(set = "pub")]
+    pub(super) worker_count: usize,
+    #[getset(set = "pub")]
+    pub(super) worker_queue_size: usize,
+    /// Titles with their own access keys, matched to clients by the signature of their first SYN packet.
+    /// Without any, every client uses the server's own access key and versions.
+    #[getset(set = "pub")]
+    pub(super) titles: Vec<TitleSettings>,
+    #[getset(set = "pub")]
+    pub(super) connection_migration: ConnectionMigration,
+    /// Connection migration always checks signatures, since it uses them to find the connection.
+    #[getset(set = "pub")]
+    pub(super) signature_policy: SignaturePolicy,
+    /// The most bytes a client can have waiting to be sent or acknowledged.
+    #[getset(set = "pub")]
+    pub(super) max_queued_bytes: usize,
+    /// Whether to use `recvmmsg`/`sendmmsg` on Linux. Other platforms always send one datagram at a time.
+    #[getset(set = "pub")]
+    pub(super) batched_io: bool,
+    /// The most datagrams received or sent in one batch.
+    #[getset(set = "pub")]
+    pub(super) io_batch_size: usize,
+    /// How many SO_REUSEPORT sockets to receive on, on Linux.
+    /// More than one needs a fixed port to bind to.
+    #[getset(set = "pub")]
+    pub(super) socket_count: usize,
 }
 
 impl ServerSettings {
-    pub fn create_client_context(&self) -> ClientContext {
-        ClientContext::new(self.flags_version, &self.access_key)
+    pub fn builder() -> ServerSettingsBuilder {
+        ServerSettingsBuilder::default()
+    }
+
+    /// Checks every setting, along with the combinations that can't work together.
+    pub fn validate(&self) -> SettingsResult<()> {
+        if self.ping_timeout == 0 {
+            return Err(SettingsError::InvalidPingTimeout);
+        }
+
+        if self.checksum_version > MAX_CHECKSUM_VERSION {
+            return Err(SettingsError::InvalidChecksumVersion {
+                checksum_version: self.checksum_version,
+            });
+        }
+
+        validate_range("worker_count", self.worker_count, 1, usize::MAX)?;
+        validate_range("worker_queue_size", self.worker_queue_size, 1, usize::MAX)?;
+        validate_range("io_batch_size", self.io_batch_size, 1, MAX_IO_BATCH_SIZE)?;
+        validate_range("socket_count", self.socket_count, 1, u8::MAX.into())?;
+
+        let titles = if self.titles.is_empty() {
+            vec![self.default_title()]
+        } else {
+            self.titles.clone()
+        };
+        let mut access_keys = HashSet::new();
+
+        for title in titles {
+            title.validate()?;
+
+            if self.max_queued_bytes < title.fragment_size.into() {
+                return Err(SettingsError::QueueSmallerThanFragment {
+                    max_queued_bytes: self.max_queued_bytes,
+                    fragment_size: title.fragment_size,
+                });
+            }
+
+            if !access_keys.insert(title.access_key.clone()) {
+                return Err(SettingsError::DuplicateAccessKey {
+                    access_key: title.access_key,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_title(&mut self, title: TitleSettings) -> &mut Self {
+        self.titles.push(title);
+        self
+    }
+
+    fn default_title(&self) -> TitleSettings {
+        TitleSettings {
+            access_key: self.access_key.clone(),
+            nex_version: self.nex_version,
+            flags_version: self.flags_version,
+            fragment_size: self.fragment_size,
+        }
+    }
+
+    /// Reads a SYN packet and finds the title whose access key signed it.
+    /// The packet is read with each title's flags version, since the layout of its flags depends on it.
+    /// If none match, the first title is used so the packet fails validation as usual.
+    pub fn find_title(&self, data: &[u8]) -> PacketResult<(TitleSettings, PacketV1)> {
+        let titles = if self.titles.is_empty() {
+            vec![self.default_title()]
+        } else {
+            self.titles.clone()
+        };
+
+        for title in &titles {
+            let Ok(packet) = PacketV1::read_packet(data, title.flags_version) else {
+                continue;
+            };
+
+            if packet.get_packet_type() == PacketType::Syn
+                && packet
+                    .validate_signature(&SignatureContext::new(&title.access_key))
+                    .is_ok()
+            {
+                return Ok((title.clone(), packet));
+            }
+        }
+
+        let title = titles[0].clone();
+        let packet = PacketV1::read_packet(data, title.flags_version)?;
+        Ok((title, packet))
+    }
+
+    pub fn create_client_context(&self, title: &TitleSettings) -> ClientContext {
+        let mut context = ClientContext::new(title.flags_version, &title.access_key);
+        context
+            .set_nex_version(title.nex_version)
+            .set_fragment_size(title.fragment_size);
+        context
     }
 }
 
@@ -31,6 +262,120 @@ impl Default for ServerSettings {
             ping_timeout: 5,
             flags_version: 1,
             checksum_version: 1,
+            worker_count: thread::available_parallelism()
+                .map(NonZeroUsize::get)
+                .unwrap_or(4),
+            worker_queue_size: 1024,
+            titles: vec![],
+            connection_migration: ConnectionMigration::Disabled,
+            signature_policy: SignaturePolicy::Strict,
+            max_queued_bytes: 4 * 1024 * 1024,
+            batched_io: true,
+            io_batch_size: 32,
+            socket_count: 1,
         }
     }
 }
+
+fn validate_range(name: &'static str, value: usize, min: usize, max: usize) -> SettingsResult<()> {
+    if value < min || value > max {
+        return Err(SettingsError::OutOfRange {
+            name,
+            value,
+            min,
+            max,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::PacketFlag;
+
+    fn new_syn_packet(access_key: &str) -> Vec<u8> {
+        new_syn_packet_with_flags_version(access_key, 1)
+    }
+
+    fn new_syn_packet_with_flags_version(access_key: &str, flags_version: u32) -> Vec<u8> {
+        let mut packet = PacketV1::default();
+        packet.set_supported_functions(flags_version);
+        packet.set_packet_type(PacketType::Syn);
+        packet.set_flags(PacketFlag::Ack | PacketFlag::NeedsAck);
+        packet
+            .to_bytes(&SignatureContext::new(access_key))
+            .expect("Should have succeeded!")
+    }
+
+    fn new_title(access_key: &str, nex_version: u32) -> TitleSettings {
+        TitleSettings {
+            access_key: access_key.to_string(),
+            nex_version,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_find_the_title_that_signed_a_syn_packet() {
+        let mut settings = ServerSettings::default();
+        settings
+            .add_title(new_title("ridfebb9", 2))
+            .add_title(new_title("6f599f81", 3));
+
+        let (title, packet) = settings
+            .find_title(&new_syn_packet("6f599f81"))
+            .expect("Should have succeeded!");
+        assert_eq!(title, new_title("6f599f81", 3));
+        assert_eq!(packet.get_packet_type(), PacketType::Syn);
+
+        let context = settings.create_client_context(&title);
+        assert_eq!(context.nex_version(), 3);
+    }
+
+    #[test]
+    fn should_fall_back_to_the_first_title() {
+        let mut settings = ServerSettings::default();
+        settings
+            .add_title(new_title("ridfebb9", 2))
+            .add_title(new_title("6f599f81", 3));
+
+        let (title, _) = settings
+            .find_title(&new_syn_packet("9f2b4678"))
+            .expect("Should have succeeded!");
+        assert_eq!(title, new_title("ridfebb9", 2));
+    }
+
+    #[test]
+    fn should_use_the_server_settings_without_titles() {
+        let mut settings = ServerSettings::default();
+        settings
+            .set_access_key("ridfebb9".to_string())
+            .set_nex_version(2);
+
+        let (title, _) = settings
+            .find_title(&new_syn_packet("6f599f81"))
+            .expect("Should have succeeded!");
+        assert_eq!(title, new_title("ridfebb9", 2));
+    }
+
+    #[test]
+    fn should_read_syn_packets_with_the_title_flags_version() {
+        let mut settings = ServerSettings::default();
+        settings
+            .add_title(new_title("ridfebb9", 2))
+            .add_title(TitleSettings {
+                flags_version: 0,
+                ..new_title("6f599f81", 0)
+            });
+
+        let data = new_syn_packet_with_flags_version("6f599f81", 0);
+        assert!(PacketV1::is_syn_packet(&data));
+
+        let (title, packet) = settings.find_title(&data).expect("Should have succeeded!");
+        assert_eq!(title.access_key, "6f599f81");
+        assert_eq!(packet.get_packet_type(), PacketType::Syn);
+        assert!(packet.get_flags().needs_ack());
+    }
+}
@@ -0,0 +1,317 @@
+use super::{
+    ConnectionMigration, ServerSettings, SettingsError, SettingsResult, SignaturePolicy,
+    TitleSettings,
+};
+use getset::Setters;
+use serde::Deserialize;
+use std::{env, fs, path::Path, str::FromStr};
+
+/// Builds validated [ServerSettings].
+/// Settings that aren't set keep their defaults.
+#[derive(Debug, Deserialize, Setters)]
+#[serde(default, deny_unknown_fields)]
+#[getset(set = "pub")]
+pub struct ServerSettingsBuilder {
+    access_key: String,
+    nex_version: u32,
+    fragment_size: u16,
+    flags_version: u32,
+    ping_timeout: u32,
+    checksum_version: u32,
+    worker_count: usize,
+    worker_queue_size: usize,
+    titles: Vec<TitleSettings>,
+    connection_migration: ConnectionMigration,
+    signature_policy: SignaturePolicy,
+    max_queued_bytes: usize,
+    batched_io: bool,
+    io_batch_size: usize,
+    socket_count: usize,
+}
+
+impl Default for ServerSettingsBuilder {
+    fn default() -> Self {
+        let defaults = ServerSettings::default();
+        Self {
+            access_key: defaults.access_key,
+            nex_version: defaults.nex_version,
+            fragment_size: defaults.fragment_size,
+            flags_version: defaults.flags_version,
+            ping_timeout: defaults.ping_timeout,
+            checksum_version: defaults.checksum_version,
+            worker_count: defaults.worker_count,
+            worker_queue_size: defaults.worker_queue_size,
+            titles: defaults.titles,
+            connection_migration: defaults.connection_migration,
+            signature_policy: defaults.signature_policy,
+            max_queued_bytes: defaults.max_queued_bytes,
+            batched_io: defaults.batched_io,
+            io_batch_size: defaults.io_batch_size,
+            socket_count: defaults.socket_count,
+        }
+    }
+}
+
+impl ServerSettingsBuilder {
+    pub fn add_title(&mut self, title: TitleSettings) -> &mut Self {
+        self.titles.push(title);
+        self
+    }
+
+    pub fn from_toml_str(toml: &str) -> SettingsResult<Self> {
+        toml::from_str(toml).map_err(|error| SettingsError::Parse {
+            message: error.to_string(),
+        })
+    }
+
+    pub fn from_toml_file(path: impl AsRef<Path>) -> SettingsResult<Self> {
+        let path = path.as_ref();
+        let toml = fs::read_to_string(path).map_err(|error| SettingsError::ReadFile {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        Self::from_toml_str(&toml)
+    }
+
+    /// Overrides settings with environment variables named after them,
+    /// such as `NEX_FRAGMENT_SIZE` for a `NEX_` prefix.
+    /// Titles can only be set in code or settings files.
+    pub fn with_env(&mut self, prefix: &str) -> SettingsResult<&mut Self> {
+        self.with_vars(prefix, env::vars())
+    }
+
+    fn with_vars(
+        &mut self,
+        prefix: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> SettingsResult<&mut Self> {
+        for (name, value) in vars {
+            let Some(setting) = name.strip_prefix(prefix) else {
+                continue;
+            };
+
+            match setting {
+                "ACCESS_KEY" => self.access_key = value,
+                "NEX_VERSION" => self.nex_version = parse_var(&name, &value)?,
+                "FRAGMENT_SIZE" => self.fragment_size = parse_var(&name, &value)?,
+                "FLAGS_VERSION" => self.flags_version = parse_var(&name, &value)?,
+                "PING_TIMEOUT" => self.ping_timeout = parse_var(&name, &value)?,
+                "CHECKSUM_VERSION" => self.checksum_version = parse_var(&name, &value)?,
+                "WORKER_COUNT" => self.worker_count = parse_var(&name, &value)?,
+                "WORKER_QUEUE_SIZE" => self.worker_queue_size = parse_var(&name, &value)?,
+                "CONNECTION_MIGRATION" => self.connection_migration = parse_var(&name, &value)?,
+                "SIGNATURE_POLICY" => self.signature_policy = parse_var(&name, &value)?,
+                "MAX_QUEUED_BYTES" => self.max_queued_bytes = parse_var(&name, &value)?,
+                "BATCHED_IO" => self.batched_io = parse_var(&name, &value)?,
+                "IO_BATCH_SIZE" => self.io_batch_size = parse_var(&name, &value)?,
+                "SOCKET_COUNT" => self.socket_count = parse_var(&name, &value)?,
+                _ => {}
+            }
+        }
+
+        Ok(self)
+    }
+
+    pub fn build(&self) -> SettingsResult<ServerSettings> {
+        let settings = ServerSettings {
+            access_key: self.access_key.clone(),
+            nex_version: self.nex_version,
+            fragment_size: self.fragment_size,
+            flags_version: self.flags_version,
+            ping_timeout: self.ping_timeout,
+            checksum_version: self.checksum_version,
+            worker_count: self.worker_count,
+            worker_queue_size: self.worker_queue_size,
+            titles: self.titles.clone(),
+            connection_migration: self.connection_migration,
+            signature_policy: self.signature_policy,
+            max_queued_bytes: self.max_queued_bytes,
+            batched_io: self.batched_io,
+            io_batch_size: self.io_batch_size,
+            socket_count: self.socket_count,
+        };
+
+        settings.validate()?;
+        Ok(settings)
+    }
+}
+
+fn parse_var<T: FromStr>(name: &str, value: &str) -> SettingsResult<T> {
+    value.parse().map_err(|_| SettingsError::InvalidEnvVar {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::MAX_FRAGMENT_SIZE;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn should_build_with_defaults() {
+        let settings = ServerSettings::builder()
+            .set_access_key("ridfebb9".to_string())
+            .set_nex_version(2)
+            .build()
+            .expect("Should have succeeded!");
+
+        assert_eq!(settings.access_key, "ridfebb9");
+        assert_eq!(settings.nex_version, 2);
+        assert_eq!(settings.fragment_size, 1300);
+    }
+
+    #[test]
+    fn should_load_from_toml() {
+        let settings = ServerSettingsBuilder::from_toml_str(
+            r#"
+            ping_timeout = 10
+            signature_policy = "log_only"
+
+            [[titles]]
+            access_key = "ridfebb9"
+            nex_version = 2
+
+            [[titles]]
+            access_key = "6f599f81"
+            nex_version = 3
+            fragment_size = 1000
+            "#,
+        )
+        .expect("Should have succeeded!")
+        .build()
+        .expect("Should have succeeded!");
+
+        assert_eq!(settings.ping_timeout, 10);
+        assert_eq!(settings.signature_policy, SignaturePolicy::LogOnly);
+        assert_eq!(settings.titles.len(), 2);
+        assert_eq!(settings.titles[1].fragment_size, 1000);
+        assert_eq!(settings.titles[1].flags_version, 1);
+    }
+
+    #[test]
+    fn should_reject_unknown_toml_settings() {
+        let result = ServerSettingsBuilder::from_toml_str("fragment_sise = 1000");
+        assert!(matches!(result, Err(SettingsError::Parse { .. })));
+    }
+
+    #[test]
+    fn should_override_settings_with_env_vars() {
+        let settings = ServerSettingsBuilder::from_toml_str("fragment_size = 1000")
+            .expect("Should have succeeded!")
+            .with_vars(
+                "NEX_",
+                vars(&[
+                    ("NEX_FRAGMENT_SIZE", "900"),
+                    ("NEX_CONNECTION_MIGRATION", "same_ip"),
+                    ("NEX_BATCHED_IO", "false"),
+                    ("OTHER_PING_TIMEOUT", "0"),
+                ]),
+            )
+            .expect("Should have succeeded!")
+            .build()
+            .expect("Should have succeeded!");
+
+        assert_eq!(settings.fragment_size, 900);
+        assert_eq!(settings.connection_migration, ConnectionMigration::SameIp);
+        assert!(!settings.batched_io);
+        assert_eq!(settings.ping_timeout, 5);
+    }
+
+    #[test]
+    fn should_layer_code_defaults_under_env_vars() {
+        let settings = ServerSettings::builder()
+            .set_fragment_size(900)
+            .set_ping_timeout(10)
+            .with_vars("NEX_", vars(&[("NEX_PING_TIMEOUT", "20")]))
+            .expect("Should have succeeded!")
+            .build()
+            .expect("Should have succeeded!");
+
+        assert_eq!(settings.fragment_size, 900);
+        assert_eq!(settings.ping_timeout, 20);
+    }
+
+    #[test]
+    fn should_reject_invalid_env_vars() {
+        let mut builder = ServerSettings::builder();
+        let result = builder.with_vars("NEX_", vars(&[("NEX_SIGNATURE_POLICY", "sometimes")]));
+        assert_eq!(
+            result.err(),
+            Some(SettingsError::InvalidEnvVar {
+                name: "NEX_SIGNATURE_POLICY".to_string(),
+                value: "sometimes".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_settings() {
+        assert_eq!(
+            ServerSettings::builder().set_fragment_size(0).build().err(),
+            Some(SettingsError::InvalidFragmentSize {
+                fragment_size: 0,
+                max_fragment_size: MAX_FRAGMENT_SIZE,
+            })
+        );
+        assert_eq!(
+            ServerSettings::builder().set_ping_timeout(0).build().err(),
+            Some(SettingsError::InvalidPingTimeout)
+        );
+        assert_eq!(
+            ServerSettings::builder()
+                .set_checksum_version(2)
+                .build()
+                .err(),
+            Some(SettingsError::InvalidChecksumVersion {
+                checksum_version: 2
+            })
+        );
+        assert_eq!(
+            ServerSettings::builder()
+                .set_nex_version(3)
+                .set_flags_version(0)
+                .build()
+                .err(),
+            Some(SettingsError::IncompatibleVersions {
+                nex_version: 3,
+                flags_version: 0,
+            })
+        );
+        assert_eq!(
+            ServerSettings::builder()
+                .set_max_queued_bytes(100)
+                .build()
+                .err(),
+            Some(SettingsError::QueueSmallerThanFragment {
+                max_queued_bytes: 100,
+                fragment_size: 1300,
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_titles_sharing_an_access_key() {
+        let title = TitleSettings {
+            access_key: "ridfebb9".to_string(),
+            ..Default::default()
+        };
+        let result = ServerSettings::builder()
+            .add_title(title.clone())
+            .add_title(title)
+            .build();
+
+        assert_eq!(
+            result.err(),
+            Some(SettingsError::DuplicateAccessKey {
+                access_key: "ridfebb9".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use super::{BaseServer, EventHandler, Server, ServerResult, ServerSettings};
+use crate::{
+    client::ClientConnection, packet::PacketV1, result::Error as NexError, rmc::RMCRequest,
+};
+use async_trait::async_trait;
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// A server for testing the provided methods of [Server], which records the errors it's given.
+/// Claimed send queues are handed to a pump that never runs them, so sends return right away.
+pub(crate) struct TestServer {
+    base: BaseServer,
+    pub errors: Mutex<Vec<String>>,
+    _send_pump: UnboundedReceiver<ClientConnection>,
+}
+
+impl TestServer {
+    pub fn new(settings: ServerSettings) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut base = BaseServer::new(settings);
+        base.send_pump = Some(sender);
+
+        Self {
+            base,
+            errors: Mutex::new(vec![]),
+            _send_pump: receiver,
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for TestServer {
+    async fn on_syn(&self, _client: &mut ClientConnection, _packet: &PacketV1) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_connect(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_data(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_disconnect(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_ping(
+        &self,
+        _client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_rmc_request(
+        &self,
+        _client: &mut ClientConnection,
+        _rmc_request: &RMCRequest,
+    ) -> ServerResult<()> {
+        Ok(())
+    }
+    async fn on_protocol_method(&self, _method_name: String) {}
+    async fn on_error(&self, error: &NexError) {
+        self.errors.lock().unwrap().push(error.to_string());
+    }
+}
+
+#[async_trait]
+impl Server for TestServer {
+    fn get_base(&self) -> &BaseServer {
+        &self.base
+    }
+
+    fn get_mut_base(&mut self) -> &mut BaseServer {
+        &mut self.base
+    }
+}
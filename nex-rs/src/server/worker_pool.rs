@@ -0,0 +1,71 @@
+use super::Server;
+use bytes::BytesMut;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    sync::Arc,
+};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+type QueuedMessage = (BytesMut, SocketAddr);
+
+/// A fixed set of packet workers with bounded queues.
+/// Every peer is pinned to a single worker, so packets from one peer
+/// are always handled in the order they arrived.
+pub struct PacketWorkerPool {
+    workers: Vec<Sender<QueuedMessage>>,
+}
+
+impl PacketWorkerPool {
+    pub fn new<T: Server + Sized + Send + Sync + 'static>(
+        server: &Arc<T>,
+        worker_count: usize,
+        queue_size: usize,
+    ) -> Self {
+        let workers = (0..worker_count.max(1))
+            .map(|_| {
+                let (sender, receiver) = mpsc::channel(queue_size.max(1));
+                tokio::spawn(Self::run_worker(Arc::clone(server), receiver));
+                sender
+            })
+            .collect();
+
+        Self { workers }
+    }
+
+    fn worker_index(&self, peer: &SocketAddr) -> usize {
+        let mut hasher = DefaultHasher::new();
+        peer.hash(&mut hasher);
+        (hasher.finish() % self.workers.len() as u64) as usize
+    }
+
+    /// Queues a message for the peer's worker.
+    /// Returns false if the message was dropped because the queue is full.
+    pub fn queue_message(&self, message: BytesMut, peer: SocketAddr) -> bool {
+        let worker = &self.workers[self.worker_index(&peer)];
+        worker.try_send((message, peer)).is_ok()
+    }
+
+    async fn run_worker<T: Server + Sized + Send + Sync + 'static>(
+        server: Arc<T>,
+        mut receiver: Receiver<QueuedMessage>,
+    ) {
+        while let Some((message, peer)) = receiver.recv().await {
+            match server.handle_socket_message(message, peer).await {
+                Ok(Some(mut client)) => {
+                    // Handlers run on their own task so a slow handler
+                    // can't hold up the other peers on this worker
+                    if client.start_rmc_dispatch() {
+                        let server = Arc::clone(&server);
+                        tokio::spawn(async move {
+                            server.run_rmc_requests(&mut client).await;
+                        });
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => server.on_error(&error.into()).await,
+            }
+        }
+    }
+}